@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    ops::Range,
+};
+
+use crate::matcher::{fold_case, Matcher};
+
+// Searches for many plain strings at once with the Aho-Corasick algorithm. The
+// patterns are put into a trie, and every state gets a failure link to the
+// state for the longest suffix of it that is also in the trie. Following the
+// failure links when a char doesn't continue the current state means the
+// haystack is only walked once, however many patterns there are.
+//
+// Like the other matchers this returns the leftmost match, and the longest one
+// when several matches start at the same place.
+pub struct AhoCorasick {
+    states: Vec<State>,
+    ignore_case: bool,
+}
+
+#[derive(Default)]
+struct State {
+    next: HashMap<char, usize>,
+    fail: usize,
+    // The number of chars from the root, i.e. the length of the string this
+    // state stands for.
+    depth: usize,
+    // Whether a pattern ends exactly at this state.
+    terminal: bool,
+    // The nearest state along the failure links where a pattern ends. A
+    // pattern that ends there is a suffix of this state too.
+    output: Option<usize>,
+}
+
+// The root of the trie is always the first state.
+const ROOT: usize = 0;
+
+impl AhoCorasick {
+    /// Builds the automaton for the patterns. When ignoring case the patterns
+    /// are case folded, and so is the haystack while it is searched.
+    pub fn new<S: AsRef<str>>(patterns: &[S], ignore_case: bool) -> AhoCorasick {
+        let mut states = vec![State::default()];
+
+        for pattern in patterns {
+            let mut state = ROOT;
+            let chars = pattern.as_ref().chars();
+            let chars: Vec<char> = if ignore_case {
+                chars.flat_map(fold_case).collect()
+            } else {
+                chars.collect()
+            };
+            for c in chars {
+                state = match states[state].next.get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        states.push(State {
+                            depth: states[state].depth + 1,
+                            ..State::default()
+                        });
+                        let next = states.len() - 1;
+                        states[state].next.insert(c, next);
+                        next
+                    }
+                };
+            }
+            states[state].terminal = true;
+        }
+
+        let mut automaton = AhoCorasick {
+            states,
+            ignore_case,
+        };
+        automaton.link();
+        automaton
+    }
+
+    // Fills in the failure and output links breadth first, so the links of
+    // every shallower state are done by the time a state needs them.
+    fn link(&mut self) {
+        let mut queue = VecDeque::from([ROOT]);
+        while let Some(state) = queue.pop_front() {
+            let children: Vec<(char, usize)> = self.states[state]
+                .next
+                .iter()
+                .map(|(&c, &s)| (c, s))
+                .collect();
+            for (c, child) in children {
+                let fail = if state == ROOT {
+                    ROOT
+                } else {
+                    self.step(self.states[state].fail, c)
+                };
+                self.states[child].fail = fail;
+                self.states[child].output = if self.states[fail].terminal {
+                    Some(fail)
+                } else {
+                    self.states[fail].output
+                };
+                queue.push_back(child);
+            }
+        }
+    }
+
+    fn step(&self, mut state: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.states[state].next.get(&c) {
+                return next;
+            }
+            if state == ROOT {
+                return ROOT;
+            }
+            state = self.states[state].fail;
+        }
+    }
+
+    // Returns the states of the patterns that end in a state, from the
+    // longest pattern to the shortest.
+    fn outputs(&self, state: usize) -> impl Iterator<Item = usize> + '_ {
+        let first = if self.states[state].terminal {
+            Some(state)
+        } else {
+            self.states[state].output
+        };
+        std::iter::successors(first, |&s| self.states[s].output)
+    }
+}
+
+impl Matcher for AhoCorasick {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        // The automaton runs on folded chars, which don't line up with the
+        // bytes of the haystack. For every folded char this remembers the
+        // offset of the char it came from, and whether it is the first char
+        // of the fold. A match has to start and end on a char boundary of the
+        // haystack to be reported.
+        let mut units: Vec<(usize, bool)> = Vec::new();
+        let mut state = ROOT;
+        let mut best: Option<Range<usize>> = None;
+        let mut pos = start;
+
+        loop {
+            for output in self.outputs(state) {
+                let begin = units.len() - self.states[output].depth;
+                let begin = match units.get(begin) {
+                    Some(&(offset, true)) => offset,
+                    Some(_) => continue,
+                    None => pos,
+                };
+                // The ends only grow, so a match that starts at the same place
+                // as the best one is longer.
+                if best.as_ref().is_none_or(|best| begin <= best.start) {
+                    best = Some(begin..pos);
+                }
+                break;
+            }
+
+            // Any match that is still in progress started at least as far
+            // back as the current state reaches, so once that is past the
+            // best match nothing can beat it.
+            if let Some(best) = &best {
+                let reach = units.len() - self.states[state].depth;
+                if units.get(reach).map_or(pos, |&(offset, _)| offset) > best.start {
+                    break;
+                }
+            }
+
+            let Some(c) = haystack[pos..].chars().next() else {
+                break;
+            };
+            let mut first = true;
+            let mut feed = |folded| {
+                units.push((pos, first));
+                first = false;
+                state = self.step(state, folded);
+            };
+            if self.ignore_case {
+                fold_case(c).for_each(&mut feed);
+            } else {
+                feed(c);
+            }
+            pos += c.len_utf8();
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::find_iter;
+
+    fn find_all(patterns: &[&str], ignore_case: bool, haystack: &str) -> Vec<Range<usize>> {
+        let matcher = AhoCorasick::new(patterns, ignore_case);
+        find_iter(&matcher, haystack).collect()
+    }
+
+    #[test]
+    fn many_patterns() {
+        assert_eq!(
+            vec![0..4, 8..12, 17..21],
+            find_all(
+                &["Rust", "fast", "duct"],
+                false,
+                "Rust is fast, productive."
+            )
+        );
+        assert_eq!(
+            Vec::<Range<usize>>::new(),
+            find_all(&["cat", "dog"], false, "Rust")
+        );
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        assert_eq!(vec![0..4], find_all(&["ab", "abcd", "bc"], false, "abcd"));
+        assert_eq!(vec![1..3], find_all(&["bcd", "bc"], false, "abce"));
+        assert_eq!(vec![0..3], find_all(&["abc", "b"], false, "abc"));
+        // The failure links have to find "bcd" after "abcx" falls through.
+        assert_eq!(vec![1..4], find_all(&["abcx", "bcd"], false, "abcd"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        assert_eq!(
+            vec![4..11, 12..15],
+            find_all(&["STRASSE", "foo"], true, "Die Straße Foo")
+        );
+        // Half of a 'ß' isn't a match.
+        assert_eq!(vec![3..4], find_all(&["s"], true, "ß s"));
+    }
+}
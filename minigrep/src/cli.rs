@@ -0,0 +1,688 @@
+use std::{env, error::Error, fmt, fs};
+
+use crate::{
+    printer::{ColorChoice, OutputMode},
+    Config, Fuzzy, InputFormat, Walker,
+};
+
+pub const USAGE: &str = "\
+Usage: minigrep [OPTIONS] QUERY PATH...
+       minigrep [OPTIONS] -e PATTERN... PATH...
+       minigrep --interactive [OPTIONS] [-e QUERY] [PATH...]
+       minigrep index [--hidden] [--no-ignore] [DIR...]
+
+Searches for QUERY in each PATH. Directories are searched recursively.
+
+`minigrep --interactive` browses the results in the terminal as QUERY is
+typed, searching . if no PATH is given. The arrow keys move through the
+matches with a preview of the lines around them, and Enter opens the selected
+one in $EDITOR. Only the options for matching and choosing files apply.
+
+`minigrep index` builds a trigram index of each DIR (default: .), or updates
+it with the files that changed. Searches of an indexed directory for plain
+strings only read the files that could contain them. To search for the word
+index, use `minigrep -- index PATH...`.
+
+Options:
+  -i, --ignore-case          Match without regard to case
+  -s, --case-sensitive       Match case exactly, overriding IGNORE_CASE
+  -S, --smart-case           Ignore case unless QUERY has an uppercase letter
+  -e, --regexp PATTERN       Search for PATTERN, can be given more than once
+  -f, --file FILE            Search for the patterns in FILE, one per line
+      --regex                Treat the patterns as regular expressions
+  -U, --multiline            Search each file as a whole, so matches can span
+                             lines. \\n in a plain QUERY matches a line ending
+      --fuzzy K              Match QUERY with up to K typos, printing the edit
+                             distance of each matching line like ~1
+  -r, --replace TEXT         Print a diff replacing each match with TEXT, where
+                             $1 or ${1} is a capture group and $$ is a $
+      --write                Write the replacements to the files instead
+      --csv                  Read records of comma separated values, with a
+                             header naming the columns on the first line
+      --tsv                  Read records of tab separated values, likewise
+      --jsonl                Read records of one JSON object per line
+      --field NAME           Only match within the column or JSON key NAME of
+                             each record, like 2, author or user.name
+  -v, --invert-match         Select the lines that don't match
+  -c, --count                Print the number of matching lines per file
+  -l, --files-with-matches   Print only the names of files with a match
+  -L, --files-without-match  Print only the names of files without a match
+      --interactive          Browse the results in a terminal UI
+      --stats                Print the matches in each file and totals of the
+                             files, bytes, lines and matches searched and the
+                             time it took
+  -m, --max-count NUM        Stop searching a file after NUM matching lines
+  -n, --line-number          Print the line number of each line
+  -b, --byte-offset          Print the byte offset of each line
+      --json                 Print results as JSON Lines
+      --color WHEN           Highlight matches: auto, always or never
+  -A, --after-context NUM    Print NUM lines after each match
+  -B, --before-context NUM   Print NUM lines before each match
+  -C, --context NUM          Print NUM lines before and after each match
+      --hidden               Search hidden files and directories, other
+                             than .git
+      --no-ignore            Don't honor .gitignore and .ignore files, and
+                             search .git along with the hidden files
+  -z, --search-zip           Also decompress standard input, and files named
+                             .gz, .bz2, .xz or .zst that don't start like one
+  -j, --threads NUM          Search NUM files at a time (default: CPU count)
+  -h, --help                 Print this help and exit
+  -V, --version              Print the version and exit
+
+Environment:
+  IGNORE_CASE                Match without regard to case when set
+  NO_COLOR                   Don't color output unless --color=always
+";
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What the command line asked minigrep to do.
+pub enum Command {
+    Search(Config),
+    /// Build or update the index of the directories.
+    Index {
+        dirs: Vec<String>,
+        walker: Walker,
+    },
+    Help,
+    Version,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ConfigError {
+    MissingQuery,
+    MissingPath,
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue { flag: String, value: String },
+    PatternFile { path: String, message: String },
+    WriteWithoutReplace,
+    Fuzzy(&'static str),
+    Field(&'static str),
+    Multiline(&'static str),
+    Interactive(&'static str),
+    Replace(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::MissingQuery => write!(f, "no query string"),
+            ConfigError::MissingPath => write!(f, "no file path"),
+            ConfigError::UnknownFlag(flag) => write!(f, "unrecognized flag `{flag}`"),
+            ConfigError::MissingValue(flag) => write!(f, "flag `{flag}` requires a value"),
+            ConfigError::UnexpectedValue(flag) => write!(f, "flag `{flag}` does not take a value"),
+            ConfigError::InvalidValue { flag, value } => {
+                write!(f, "invalid value `{value}` for flag `{flag}`")
+            }
+            ConfigError::PatternFile { path, message } => {
+                write!(f, "could not read pattern file `{path}`: {message}")
+            }
+            ConfigError::WriteWithoutReplace => write!(f, "flag `--write` requires `--replace`"),
+            ConfigError::Fuzzy(reason) => write!(f, "flag `--fuzzy` {reason}"),
+            ConfigError::Field(reason) => write!(f, "flag `--field` {reason}"),
+            ConfigError::Multiline(reason) => write!(f, "flag `--multiline` {reason}"),
+            ConfigError::Interactive(reason) => write!(f, "flag `--interactive` {reason}"),
+            ConfigError::Replace(reason) => write!(f, "flag `--replace` {reason}"),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+// Every flag has a long name and maybe a short one. The bool says whether the
+// flag takes a value.
+const FLAGS: &[(Option<char>, &str, bool)] = &[
+    (Some('i'), "ignore-case", false),
+    (Some('s'), "case-sensitive", false),
+    (Some('S'), "smart-case", false),
+    (Some('e'), "regexp", true),
+    (Some('f'), "file", true),
+    (None, "regex", false),
+    (Some('U'), "multiline", false),
+    (None, "fuzzy", true),
+    (Some('r'), "replace", true),
+    (None, "write", false),
+    (None, "csv", false),
+    (None, "tsv", false),
+    (None, "jsonl", false),
+    (None, "field", true),
+    (Some('v'), "invert-match", false),
+    (Some('c'), "count", false),
+    (Some('l'), "files-with-matches", false),
+    (Some('L'), "files-without-match", false),
+    (None, "stats", false),
+    (None, "interactive", false),
+    (Some('m'), "max-count", true),
+    (Some('n'), "line-number", false),
+    (Some('b'), "byte-offset", false),
+    (None, "json", false),
+    (None, "color", true),
+    (Some('A'), "after-context", true),
+    (Some('B'), "before-context", true),
+    (Some('C'), "context", true),
+    (None, "hidden", false),
+    (None, "no-ignore", false),
+    (Some('z'), "search-zip", false),
+    (Some('j'), "threads", true),
+    (Some('h'), "help", false),
+    (Some('V'), "version", false),
+];
+
+impl Command {
+    // Parse handles the parsing of the command line arguments into data.
+    //
+    // We originally had to use clone on the args because we didn't have
+    // ownership of the args variable, but we can take ownership of the
+    // iterator.
+    //
+    // Flags can appear anywhere and are applied in order, so later flags
+    // override earlier ones and every flag overrides the environment. Short
+    // flags can be grouped (-in) and values can be attached (-A2,
+    // --context=2). Everything after -- is positional.
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Command, ConfigError> {
+        let mut config = Config {
+            // Check if the environment variable is set.
+            ignore_case: env::var("IGNORE_CASE").is_ok(),
+            ..Config::default()
+        };
+        let mut positional = Vec::new();
+        // Whether -e or -f was given. A pattern file can be empty, so this
+        // can't be told from the patterns.
+        let mut patterns_given = false;
+
+        let mut args = args.skip(1).peekable();
+        if args.peek().is_some_and(|arg| arg == "index") {
+            args.next();
+            return Command::parse_index(args);
+        }
+
+        while let Some(arg) = args.next() {
+            if arg == "--" {
+                positional.extend(args.by_ref());
+                break;
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value.to_string())),
+                    None => (long, None),
+                };
+                let (_, name, takes_value) = FLAGS
+                    .iter()
+                    .find(|(_, long, _)| *long == name)
+                    .ok_or_else(|| ConfigError::UnknownFlag(arg.clone()))?;
+                let value = match (takes_value, value) {
+                    (true, Some(value)) => Some(value),
+                    (true, None) => Some(
+                        args.next()
+                            .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?,
+                    ),
+                    (false, Some(_)) => return Err(ConfigError::UnexpectedValue(arg.clone())),
+                    (false, None) => None,
+                };
+                patterns_given |= matches!(*name, "regexp" | "file");
+                if let Some(command) = config.apply(name, value)? {
+                    return Ok(command);
+                }
+            } else if arg.len() > 1 && arg.starts_with('-') {
+                // A lone - means standard input, so it isn't a flag.
+                let shorts = &arg[1..];
+                for (i, c) in shorts.char_indices() {
+                    let (_, name, takes_value) = FLAGS
+                        .iter()
+                        .find(|(short, _, _)| *short == Some(c))
+                        .ok_or_else(|| ConfigError::UnknownFlag(format!("-{c}")))?;
+                    let value = if *takes_value {
+                        // The rest of the group is the value, or the next
+                        // argument if there isn't anything left.
+                        let rest = &shorts[i + c.len_utf8()..];
+                        Some(if rest.is_empty() {
+                            args.next()
+                                .ok_or_else(|| ConfigError::MissingValue(format!("-{c}")))?
+                        } else {
+                            rest.to_string()
+                        })
+                    } else {
+                        None
+                    };
+                    patterns_given |= matches!(*name, "regexp" | "file");
+                    if let Some(command) = config.apply(name, value.clone())? {
+                        return Ok(command);
+                    }
+                    if value.is_some() {
+                        break;
+                    }
+                }
+            } else {
+                positional.push(arg);
+            }
+        }
+
+        let mut positional = positional.into_iter();
+        // The first positional argument is the query, unless the patterns
+        // were given with -e or -f. The interactive query is typed in later,
+        // so there only the paths are positional.
+        if !patterns_given && !config.interactive {
+            let query = positional.next().ok_or(ConfigError::MissingQuery)?;
+            config.patterns.push(query);
+        }
+        // Every remaining argument is a path to search.
+        config.paths = positional.collect();
+        if config.paths.is_empty() {
+            if !config.interactive {
+                return Err(ConfigError::MissingPath);
+            }
+            config.paths.push(".".to_string());
+        }
+        if config.interactive {
+            if config.patterns.len() > 1 {
+                return Err(ConfigError::Interactive("takes a single query"));
+            }
+            if config.multiline || config.replace.is_some() {
+                return Err(ConfigError::Interactive(
+                    "can't be used with `--multiline` or `--replace`",
+                ));
+            }
+        }
+        if config.write && config.replace.is_none() {
+            return Err(ConfigError::WriteWithoutReplace);
+        }
+        if config.replace.is_some() {
+            // The replacements are made to whole files and shown as a diff,
+            // so the flags that pick lines or change how they are printed
+            // would be ignored.
+            if config.invert_match {
+                return Err(ConfigError::Replace("can't be used with `--invert-match`"));
+            }
+            if config.max_count.is_some() {
+                return Err(ConfigError::Replace("can't be used with `--max-count`"));
+            }
+            if config.mode != OutputMode::Lines || config.json || config.color != ColorChoice::Auto
+            {
+                return Err(ConfigError::Replace(
+                    "can't be used with `--count`, `--files-with-matches`, \
+                     `--files-without-match`, `--json` or `--color`",
+                ));
+            }
+            if config.search_zip {
+                return Err(ConfigError::Replace("can't be used with `--search-zip`"));
+            }
+        }
+        if config.fuzzy.is_some() {
+            if config.regex {
+                return Err(ConfigError::Fuzzy("can't be used with `--regex`"));
+            }
+            if config.patterns.len() > 1 {
+                return Err(ConfigError::Fuzzy("takes a single pattern"));
+            }
+            // The interactive query is typed in later and checked then.
+            if config
+                .patterns
+                .first()
+                .is_some_and(|pattern| pattern.chars().count() > Fuzzy::MAX_LEN)
+            {
+                return Err(ConfigError::Fuzzy("queries can be at most 64 chars long"));
+            }
+        }
+        if config.multiline {
+            // These all look at one line at a time.
+            if config.invert_match {
+                return Err(ConfigError::Multiline(
+                    "can't be used with `--invert-match`",
+                ));
+            }
+            if config.fuzzy.is_some() {
+                return Err(ConfigError::Multiline("can't be used with `--fuzzy`"));
+            }
+            if config.replace.is_some() {
+                return Err(ConfigError::Multiline("can't be used with `--replace`"));
+            }
+            if config.format != InputFormat::Lines {
+                return Err(ConfigError::Multiline(
+                    "can't be used with `--csv`, `--tsv` or `--jsonl`",
+                ));
+            }
+        }
+        if config.field.is_some() {
+            if config.format == InputFormat::Lines {
+                return Err(ConfigError::Field("requires `--csv`, `--tsv` or `--jsonl`"));
+            }
+            if config.replace.is_some() {
+                return Err(ConfigError::Field("can't be used with `--replace`"));
+            }
+        }
+
+        Ok(Command::Search(config))
+    }
+
+    // The index subcommand only takes the flags that change which files are
+    // walked, and the directories to index.
+    fn parse_index(args: impl Iterator<Item = String>) -> Result<Command, ConfigError> {
+        let mut walker = Walker {
+            hidden: false,
+            ignore: true,
+        };
+        let mut dirs = Vec::new();
+        for arg in args {
+            match arg.as_str() {
+                "--hidden" => walker.hidden = true,
+                "--no-ignore" => walker.ignore = false,
+                "-h" | "--help" => return Ok(Command::Help),
+                _ if arg.starts_with('-') => return Err(ConfigError::UnknownFlag(arg)),
+                _ => dirs.push(arg),
+            }
+        }
+        if dirs.is_empty() {
+            dirs.push(".".to_string());
+        }
+        Ok(Command::Index { dirs, walker })
+    }
+}
+
+impl Config {
+    // Applies one flag to the config. Help and version stop the parsing, so
+    // they are returned as the command to run.
+    fn apply(&mut self, name: &str, value: Option<String>) -> Result<Option<Command>, ConfigError> {
+        let number = || -> Result<usize, ConfigError> {
+            let value = value.clone().unwrap_or_default();
+            value.parse().map_err(|_| ConfigError::InvalidValue {
+                flag: format!("--{name}"),
+                value,
+            })
+        };
+
+        match name {
+            // The three case flags override each other, so whichever comes
+            // last wins.
+            "ignore-case" => (self.ignore_case, self.smart_case) = (true, false),
+            "case-sensitive" => (self.ignore_case, self.smart_case) = (false, false),
+            "smart-case" => (self.ignore_case, self.smart_case) = (false, true),
+            "regexp" => self.patterns.push(value.unwrap_or_default()),
+            "file" => {
+                let path = value.unwrap_or_default();
+                let contents = fs::read_to_string(&path).map_err(|e| ConfigError::PatternFile {
+                    path: path.clone(),
+                    message: e.to_string(),
+                })?;
+                self.patterns.extend(contents.lines().map(String::from));
+            }
+            "regex" => self.regex = true,
+            "fuzzy" => self.fuzzy = Some(number()?),
+            "replace" => self.replace = value,
+            "write" => self.write = true,
+            "invert-match" => self.invert_match = true,
+            "count" => self.mode = OutputMode::Count,
+            "files-with-matches" => self.mode = OutputMode::FilesWithMatches,
+            "files-without-match" => self.mode = OutputMode::FilesWithoutMatch,
+            "stats" => self.stats = true,
+            "interactive" => self.interactive = true,
+            "max-count" => self.max_count = Some(number()?),
+            "line-number" => self.line_number = true,
+            "byte-offset" => self.byte_offset = true,
+            "json" => self.json = true,
+            "color" => {
+                self.color = match value.as_deref() {
+                    Some("auto") => ColorChoice::Auto,
+                    Some("always") => ColorChoice::Always,
+                    Some("never") => ColorChoice::Never,
+                    _ => {
+                        return Err(ConfigError::InvalidValue {
+                            flag: "--color".to_string(),
+                            value: value.unwrap_or_default(),
+                        })
+                    }
+                }
+            }
+            "after-context" => self.after_context = number()?,
+            "before-context" => self.before_context = number()?,
+            "context" => {
+                self.after_context = number()?;
+                self.before_context = self.after_context;
+            }
+            "hidden" => self.hidden = true,
+            "no-ignore" => self.no_ignore = true,
+            "search-zip" => self.search_zip = true,
+            "multiline" => self.multiline = true,
+            "csv" => self.format = InputFormat::Csv,
+            "tsv" => self.format = InputFormat::Tsv,
+            "jsonl" => self.format = InputFormat::Jsonl,
+            "field" => self.field = value,
+            "threads" => match number()? {
+                0 => {
+                    return Err(ConfigError::InvalidValue {
+                        flag: "--threads".to_string(),
+                        value: "0".to_string(),
+                    })
+                }
+                threads => self.threads = Some(threads),
+            },
+            "help" => return Ok(Some(Command::Help)),
+            "version" => return Ok(Some(Command::Version)),
+            _ => unreachable!("flag --{name} is missing from Config::apply"),
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn parse(args: &[&str]) -> Result<Config, ConfigError> {
+        let args = std::iter::once("minigrep").chain(args.iter().copied());
+        match Command::parse(args.map(String::from))? {
+            Command::Search(config) => Ok(config),
+            _ => panic!("expected a search command"),
+        }
+    }
+
+    #[test]
+    fn positional() {
+        let config = parse(&["nobody", "poem.txt", "src"]).unwrap();
+
+        assert_eq!(vec!["nobody"], config.patterns);
+        assert_eq!(vec!["poem.txt", "src"], config.paths);
+        assert_eq!(Err(ConfigError::MissingQuery), parse(&[]).map(|_| ()));
+        assert_eq!(
+            Err(ConfigError::MissingPath),
+            parse(&["nobody"]).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn short_and_long_flags() {
+        let config = parse(&["-in", "--regex", "t.o", "poem.txt", "--max-count=2", "-A1"]).unwrap();
+        let audit = parse(&["-L", "--stats", "t.o", "poem.txt"]).unwrap();
+
+        assert!(config.ignore_case && config.line_number && config.regex);
+        assert_eq!(Some(2), config.max_count);
+        assert_eq!(None, config.threads);
+        assert_eq!(ColorChoice::Auto, config.color);
+        assert_eq!(1, config.after_context);
+        assert_eq!(vec!["t.o"], config.patterns);
+        assert_eq!(OutputMode::FilesWithoutMatch, audit.mode);
+        assert!(audit.stats);
+
+        let browse = parse(&["--interactive", "-i"]).unwrap();
+        assert!(browse.interactive && browse.patterns.is_empty());
+        assert_eq!(vec!["."], browse.paths);
+        let browse = parse(&["--interactive", "--fuzzy", "1"]).unwrap();
+        assert!(browse.patterns.is_empty());
+        assert_eq!(Some(1), browse.fuzzy);
+    }
+
+    #[test]
+    fn later_flags_override() {
+        let config = parse(&[
+            "--color=always",
+            "--color",
+            "never",
+            "-i",
+            "--case-sensitive",
+            "-C",
+            "2",
+            "-A",
+            "1",
+            "to",
+            "-",
+        ])
+        .unwrap();
+
+        assert!(!config.ignore_case);
+        assert_eq!(ColorChoice::Never, config.color);
+        assert_eq!((2, 1), (config.before_context, config.after_context));
+        assert_eq!(vec!["-"], config.paths);
+    }
+
+    #[test]
+    fn case_flags() {
+        let config = parse(&["-i", "-S", "to", "poem.txt"]).unwrap();
+        assert!(!config.ignore_case && config.smart_case);
+        let config = parse(&["-S", "--ignore-case", "to", "poem.txt"]).unwrap();
+        assert!(config.ignore_case && !config.smart_case);
+        let config = parse(&["-S", "-s", "to", "poem.txt"]).unwrap();
+        assert!(!config.ignore_case && !config.smart_case);
+    }
+
+    #[test]
+    fn patterns() {
+        let config = parse(&["-e", "to", "poem.txt", "--regexp=-n", "src"]).unwrap();
+        assert_eq!(vec!["to", "-n"], config.patterns);
+        assert_eq!(vec!["poem.txt", "src"], config.paths);
+
+        let dir = TempDir::new("patterns");
+        let path = dir.join("patterns.txt");
+        fs::write(&path, "body\nfrog\n").unwrap();
+        let config = parse(&["-e", "to", "-f", path.to_str().unwrap(), "poem.txt"]).unwrap();
+        assert_eq!(vec!["to", "body", "frog"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.paths);
+        // An empty pattern file still counts as giving the patterns.
+        fs::write(&path, "").unwrap();
+        let config = parse(&["-f", path.to_str().unwrap(), "poem.txt"]).unwrap();
+        assert!(config.patterns.is_empty());
+        assert_eq!(vec!["poem.txt"], config.paths);
+
+        assert_eq!(
+            Err(ConfigError::MissingPath),
+            parse(&["-e", "to"]).map(|_| ())
+        );
+        assert!(matches!(
+            parse(&["-f", "/nonexistent/patterns", "poem.txt"]),
+            Err(ConfigError::PatternFile { .. })
+        ));
+    }
+
+    #[test]
+    fn index() {
+        let args = ["minigrep", "index", "--hidden", "src", "tests"].map(String::from);
+        match Command::parse(args.into_iter()) {
+            Ok(Command::Index { dirs, walker }) => {
+                assert_eq!(vec!["src", "tests"], dirs);
+                assert!(walker.hidden && walker.ignore);
+            }
+            _ => panic!("expected an index command"),
+        }
+
+        let config = parse(&["--", "index", "poem.txt"]).unwrap();
+        assert_eq!(vec!["index"], config.patterns);
+    }
+
+    #[test]
+    fn double_dash() {
+        let config = parse(&["--", "-n", "poem.txt"]).unwrap();
+
+        assert!(!config.line_number);
+        assert_eq!(vec!["-n"], config.patterns);
+    }
+
+    #[test]
+    fn help_and_version() {
+        let args = ["minigrep", "-V", "--bogus"].map(String::from);
+        assert!(matches!(
+            Command::parse(args.into_iter()),
+            Ok(Command::Version)
+        ));
+        let args = ["minigrep", "nobody", "--help"].map(String::from);
+        assert!(matches!(
+            Command::parse(args.into_iter()),
+            Ok(Command::Help)
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            Err(ConfigError::UnknownFlag("--bogus".to_string())),
+            parse(&["--bogus", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::MissingValue("-m".to_string())),
+            parse(&["to", "poem.txt", "-m"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::UnexpectedValue("--count=3".to_string())),
+            parse(&["--count=3", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Fuzzy("can't be used with `--regex`")),
+            parse(&["--fuzzy=1", "--regex", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Fuzzy("takes a single pattern")),
+            parse(&["--fuzzy", "1", "-e", "to", "-e", "me", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::WriteWithoutReplace),
+            parse(&["--write", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Replace("can't be used with `--max-count`")),
+            parse(&["-r", "x", "-m1", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Replace("can't be used with `--invert-match`")),
+            parse(&["-v", "-r", "x", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Replace("can't be used with `--search-zip`")),
+            parse(&["-zr", "x", "to", "poem.txt"]).map(|_| ())
+        );
+        for flag in ["-c", "-l", "-L", "--json", "--color=never"] {
+            assert!(matches!(
+                parse(&["-r", "x", flag, "to", "poem.txt"]),
+                Err(ConfigError::Replace(_))
+            ));
+        }
+        assert_eq!(
+            Err(ConfigError::Field("requires `--csv`, `--tsv` or `--jsonl`")),
+            parse(&["--field", "name", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Field("can't be used with `--replace`")),
+            parse(&["--jsonl", "--field=msg", "-r", "x", "to", "log"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::Multiline(
+                "can't be used with `--invert-match`"
+            )),
+            parse(&["-Uv", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: "--threads".to_string(),
+                value: "0".to_string()
+            }),
+            parse(&["-j0", "to", "poem.txt"]).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidValue {
+                flag: "--context".to_string(),
+                value: "many".to_string()
+            }),
+            parse(&["-C", "many", "to", "poem.txt"]).map(|_| ())
+        );
+    }
+}
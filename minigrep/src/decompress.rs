@@ -0,0 +1,281 @@
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    process::{Child, ChildStdout, Command, Stdio},
+    thread::{self, JoinHandle},
+};
+
+// The compression formats that can be searched, by the magic bytes their files
+// start with, the extensions they usually have and the program that
+// decompresses them to standard output. Like ripgrep, the decompressing is
+// left to those programs rather than reimplemented here.
+const FORMATS: &[Format] = &[
+    Format {
+        magic: &[0x1f, 0x8b],
+        extensions: &["gz", "tgz"],
+        program: "gzip",
+        args: &["-dc"],
+    },
+    Format {
+        magic: b"BZh",
+        extensions: &["bz2", "tbz2"],
+        program: "bzip2",
+        args: &["-dc"],
+    },
+    Format {
+        magic: &[0xfd, b'7', b'z', b'X', b'Z', 0x00],
+        extensions: &["xz", "txz"],
+        program: "xz",
+        args: &["-dc"],
+    },
+    Format {
+        magic: &[0x28, 0xb5, 0x2f, 0xfd],
+        extensions: &["zst", "tzst"],
+        program: "zstd",
+        args: &["-dcq"],
+    },
+];
+
+struct Format {
+    magic: &'static [u8],
+    extensions: &'static [&'static str],
+    program: &'static str,
+    args: &'static [&'static str],
+}
+
+/// Opens a file to search, or standard input for a path of -. Compressed files
+/// are decompressed as they are read.
+///
+/// A file is treated as compressed when it starts with the magic bytes of a
+/// format, whatever it is called, so rotated logs like `app.log.1` are
+/// decompressed too. With `force`, standard input is looked at the same way,
+/// and a file with the extension of a format that doesn't start like one is
+/// still given to its decompressor, which says if it can't make sense of it.
+pub fn open(path: &Path, force: bool) -> io::Result<Box<dyn BufRead>> {
+    if path == Path::new("-") {
+        // Standard input has no name, so it's only decompressed when forced.
+        // Looking at the buffer doesn't use it up, so the decompressor still
+        // gets the whole input.
+        let format = if force {
+            detect(io::stdin().lock().fill_buf()?)
+        } else {
+            None
+        };
+        return match format {
+            Some(format) => decompress(format, io::stdin()).map(|r| Box::new(r) as _),
+            None => Ok(Box::new(io::stdin().lock())),
+        };
+    }
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or("");
+    let format = detect(reader.fill_buf()?).or_else(|| {
+        FORMATS
+            .iter()
+            .find(|format| force && format.extensions.contains(&extension))
+    });
+    match format {
+        Some(format) => decompress(format, reader).map(|r| Box::new(r) as _),
+        None => Ok(Box::new(reader)),
+    }
+}
+
+fn detect(start: &[u8]) -> Option<&'static Format> {
+    FORMATS
+        .iter()
+        .find(|format| start.starts_with(format.magic))
+}
+
+// Starts the decompressor with a thread feeding it the compressed input. The
+// thread stops on its own once the input runs out, or once the decompressor
+// is gone because the search stopped early.
+fn decompress(
+    format: &'static Format,
+    mut input: impl Read + Send + 'static,
+) -> io::Result<Decompressor> {
+    let mut child = Command::new(format.program)
+        .args(format.args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("couldn't run `{}` to decompress: {e}", format.program),
+            )
+        })?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    thread::spawn(move || io::copy(&mut input, &mut stdin));
+    let stdout = BufReader::new(child.stdout.take().unwrap());
+    // The error messages are read while the output is, on a thread of their
+    // own. A decompressor that fills the stderr pipe would otherwise block on
+    // it while we wait for the rest of its output.
+    let mut stderr = child.stderr.take().unwrap();
+    let stderr = thread::spawn(move || {
+        let mut message = Vec::new();
+        let _ = stderr.read_to_end(&mut message);
+        String::from_utf8_lossy(&message).into_owned()
+    });
+
+    Ok(Decompressor {
+        program: format.program,
+        child,
+        stdout,
+        stderr: Some(stderr),
+        finished: false,
+    })
+}
+
+// Reads the output of a decompressor. Once the output ends the exit status is
+// checked, so a corrupt file is reported instead of looking like it ended early.
+struct Decompressor {
+    program: &'static str,
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+    // Everything the decompressor wrote to stderr, once it exits.
+    stderr: Option<JoinHandle<String>>,
+    finished: bool,
+}
+
+impl Decompressor {
+    fn finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+
+        let status = self.child.wait()?;
+        let message = self
+            .stderr
+            .take()
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!(
+                "`{}` failed to decompress: {}",
+                self.program,
+                message.trim()
+            )))
+        }
+    }
+}
+
+impl Read for Decompressor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.stdout.read(buf)?;
+        if n == 0 && !buf.is_empty() {
+            self.finish()?;
+        }
+        Ok(n)
+    }
+}
+
+impl BufRead for Decompressor {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.stdout.fill_buf()?.is_empty() {
+            self.finish()?;
+        }
+        self.stdout.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.stdout.consume(amt);
+    }
+}
+
+impl Drop for Decompressor {
+    // A search that stops early, like with --max-count, leaves the
+    // decompressor running. It isn't needed anymore, so it is stopped rather
+    // than left to finish the file.
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    fn read(path: &Path, force: bool) -> io::Result<String> {
+        let mut contents = String::new();
+        open(path, force)?.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+
+    #[test]
+    fn detect_formats() {
+        assert_eq!(Some("gzip"), detect(&[0x1f, 0x8b, 8, 0]).map(|f| f.program));
+        assert_eq!(Some("bzip2"), detect(b"BZh91AY").map(|f| f.program));
+        assert!(detect(b"Rust:\n").is_none());
+        assert!(detect(b"").is_none());
+    }
+
+    #[test]
+    fn gzip() {
+        let dir = TempDir::new("gzip");
+        let path = dir.join("poem.txt");
+        fs::write(&path, "I'm nobody! Who are you?\n").unwrap();
+        let status = Command::new("gzip").arg("-k").arg(&path).status();
+        if !status.is_ok_and(|status| status.success()) {
+            // Nothing to test without gzip.
+            return;
+        }
+
+        let gz = dir.join("poem.txt.gz");
+        assert_eq!("I'm nobody! Who are you?\n", read(&gz, false).unwrap());
+        // The magic bytes are enough, like for a rotated log.
+        let renamed = dir.join("app.log.1");
+        fs::rename(&gz, &renamed).unwrap();
+        assert_eq!("I'm nobody! Who are you?\n", read(&renamed, false).unwrap());
+        assert_eq!("I'm nobody! Who are you?\n", read(&path, true).unwrap());
+        // A plain file named like a compressed one is only decompressed when
+        // forced, and then it isn't a valid one.
+        let misnamed = dir.join("plain.gz");
+        fs::copy(&path, &misnamed).unwrap();
+        assert_eq!(
+            "I'm nobody! Who are you?\n",
+            read(&misnamed, false).unwrap()
+        );
+        assert!(read(&misnamed, true).is_err());
+
+        // A corrupt file is an error, not an empty file.
+        fs::write(&gz, [0x1f, 0x8b, 1, 2, 3]).unwrap();
+        let e = read(&gz, false).unwrap_err();
+        assert!(e.to_string().starts_with("`gzip` failed to decompress: "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn noisy_decompressor() {
+        // Writes more to stderr than a pipe holds before any output, and then
+        // fails.
+        static NOISY: Format = Format {
+            magic: b"",
+            extensions: &[],
+            program: "sh",
+            args: &[
+                "-c",
+                "head -c 1000000 /dev/zero | tr '\\0' x >&2; cat; exit 1",
+            ],
+        };
+
+        let mut contents = String::new();
+        let e = decompress(&NOISY, "I'm nobody!".as_bytes())
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap_err();
+        assert_eq!("I'm nobody!", contents);
+        assert!(e.to_string().ends_with("xxx"));
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{printer::display_name, ConfigError, RegexError};
+
+/// Everything that can go wrong running minigrep.
+#[derive(Debug)]
+pub enum MinigrepError {
+    /// The command line couldn't be parsed.
+    Args(ConfigError),
+    /// A pattern isn't a valid regular expression.
+    Pattern(RegexError),
+    /// A file or directory couldn't be read. When searching, these are
+    /// reported as they happen and the other files are still searched.
+    File { path: PathBuf, source: io::Error },
+    /// Any other I/O error, like failing to write the output.
+    Io(io::Error),
+}
+
+impl MinigrepError {
+    /// The exit status for the error. Like grep, every error is 2, so it
+    /// can be told apart from a search without matches. The one exception is
+    /// a broken pipe, which isn't a failure.
+    pub fn exit_code(&self) -> i32 {
+        if self.is_broken_pipe() {
+            0
+        } else {
+            2
+        }
+    }
+
+    /// Returns true if the output was closed before everything was written,
+    /// like when piping into `head`. Whoever was reading has all they wanted,
+    /// so minigrep just stops without saying anything.
+    pub fn is_broken_pipe(&self) -> bool {
+        matches!(self, MinigrepError::Io(e) if e.kind() == io::ErrorKind::BrokenPipe)
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MinigrepError::Args(e) => write!(f, "{e}"),
+            MinigrepError::Pattern(e) => write!(f, "{e}"),
+            MinigrepError::File { path, source } => {
+                write!(f, "{}: {source}", display_name(path))
+            }
+            MinigrepError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Args(e) => Some(e),
+            MinigrepError::Pattern(e) => Some(e),
+            MinigrepError::File { source, .. } => Some(source),
+            MinigrepError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<ConfigError> for MinigrepError {
+    fn from(e: ConfigError) -> MinigrepError {
+        MinigrepError::Args(e)
+    }
+}
+
+impl From<RegexError> for MinigrepError {
+    fn from(e: RegexError) -> MinigrepError {
+        MinigrepError::Pattern(e)
+    }
+}
+
+impl From<io::Error> for MinigrepError {
+    fn from(e: io::Error) -> MinigrepError {
+        MinigrepError::Io(e)
+    }
+}
+
+/// What a search found. Like grep, the exit status is 0 if something was
+/// found, 1 if nothing was and 2 if a file couldn't be searched, even when
+/// the others matched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outcome {
+    pub matched: bool,
+    /// The number of files and directories that couldn't be read.
+    pub failed: usize,
+}
+
+impl Outcome {
+    pub fn exit_code(&self) -> i32 {
+        if self.failed > 0 {
+            2
+        } else if self.matched {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+// Errors reading the file being searched are wrapped in this, so they can be
+// told apart from errors writing the output once both come out of the printer
+// as io::Error.
+#[derive(Debug)]
+struct ReadError(io::Error);
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Error for ReadError {}
+
+/// Reads a file being searched, marking the errors as errors reading it.
+pub(crate) struct FileReader<R>(pub(crate) R);
+
+impl<R: Read> Read for FileReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf).map_err(read_error)
+    }
+}
+
+impl<R: BufRead> BufRead for FileReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.0.fill_buf().map_err(read_error)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt);
+    }
+}
+
+/// Marks an error as an error reading the file being searched, like one
+/// opening it.
+pub(crate) fn read_error(e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), ReadError(e))
+}
+
+// Turns an error from searching a file into a File error if it came from
+// reading the file, or an Io error if it came from writing the output.
+pub(crate) fn search_error(e: io::Error, path: &Path) -> MinigrepError {
+    match e.downcast::<ReadError>() {
+        Ok(read) => MinigrepError::File {
+            path: path.to_path_buf(),
+            source: read.0,
+        },
+        Err(e) => MinigrepError::Io(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fails every read, like a file on a failing disk.
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk on fire"))
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        let outcome = |matched, failed| Outcome { matched, failed }.exit_code();
+        assert_eq!(0, outcome(true, 0));
+        assert_eq!(1, outcome(false, 0));
+        assert_eq!(2, outcome(true, 1));
+        assert_eq!(
+            2,
+            MinigrepError::Io(io::ErrorKind::PermissionDenied.into()).exit_code()
+        );
+        let broken_pipe = MinigrepError::Io(io::ErrorKind::BrokenPipe.into());
+        assert!(broken_pipe.is_broken_pipe());
+        assert_eq!(0, broken_pipe.exit_code());
+        // Only the output can be a pipe that breaks.
+        let broken_pipe = MinigrepError::File {
+            path: PathBuf::from("fifo"),
+            source: io::ErrorKind::BrokenPipe.into(),
+        };
+        assert!(!broken_pipe.is_broken_pipe());
+    }
+
+    #[test]
+    fn read_errors_name_the_file() {
+        let e = FileReader(Broken).read(&mut [0; 8]).unwrap_err();
+        let e = search_error(e, Path::new("poem.txt"));
+        assert!(matches!(e, MinigrepError::File { .. }));
+        assert_eq!("poem.txt: disk on fire", e.to_string());
+
+        // Errors writing the output aren't about the file.
+        let e = search_error(io::ErrorKind::BrokenPipe.into(), Path::new("poem.txt"));
+        assert!(matches!(e, MinigrepError::Io(_)));
+    }
+}
@@ -0,0 +1,368 @@
+use std::{borrow::Cow, io, ops::Range};
+
+use crate::matcher::Matcher;
+
+/// How the lines of the input are split into fields.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum InputFormat {
+    /// Plain lines, without any fields.
+    #[default]
+    Lines,
+    /// Comma separated values. The first line is a header naming the columns.
+    Csv,
+    /// Tab separated values. The first line is a header naming the columns.
+    Tsv,
+    /// One JSON object per line.
+    Jsonl,
+}
+
+impl InputFormat {
+    /// Returns true if the first line of each file names the columns rather
+    /// than being a record.
+    pub fn has_header(self) -> bool {
+        matches!(self, InputFormat::Csv | InputFormat::Tsv)
+    }
+}
+
+// Where the field is found in each line.
+enum Field<'a> {
+    // The index of a CSV or TSV column, or None for an empty file, which
+    // doesn't have any.
+    Column { index: Option<usize>, delimiter: u8 },
+    // The keys leading to a value in a JSON object, e.g. ["user", "name"] for
+    // --field user.name.
+    Json(Vec<&'a str>),
+}
+
+/// Restricts a matcher to one field of each record. Only the field is given to
+/// the inner matcher, so `^` and `$` match at the start and end of the field,
+/// and the matches are reported at their place in the whole line.
+///
+/// Records are single lines, so quoted CSV fields can't contain newlines. In
+/// JSON the field is matched as it is written, without decoding escapes, and
+/// string values are matched without their quotes.
+pub struct FieldMatcher<'a> {
+    inner: &'a dyn Matcher,
+    field: Field<'a>,
+}
+
+impl<'a> FieldMatcher<'a> {
+    /// Builds the matcher for one file. The header is the first line of the
+    /// file, which CSV and TSV columns are looked up in. A column can also be
+    /// given by its number, starting at 1.
+    ///
+    /// Fails if the header doesn't have the column, so a misspelled name
+    /// isn't mistaken for a search without matches, or for plain lines,
+    /// which don't have fields.
+    pub fn new(
+        inner: &'a dyn Matcher,
+        format: InputFormat,
+        name: &'a str,
+        header: &str,
+    ) -> io::Result<FieldMatcher<'a>> {
+        let field = match format {
+            InputFormat::Lines => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "plain lines don't have fields",
+                ))
+            }
+            InputFormat::Csv | InputFormat::Tsv => {
+                let delimiter = if format == InputFormat::Csv {
+                    b','
+                } else {
+                    b'\t'
+                };
+                let index = csv_fields(header, delimiter)
+                    .into_iter()
+                    .position(|range| unquote(&header[range]) == name)
+                    .or_else(|| name.parse::<usize>().ok()?.checked_sub(1));
+                if index.is_none() && !header.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("no column `{name}`"),
+                    ));
+                }
+                Field::Column { index, delimiter }
+            }
+            InputFormat::Jsonl => Field::Json(name.split('.').collect()),
+        };
+        Ok(FieldMatcher { inner, field })
+    }
+
+    fn locate(&self, line: &str) -> Option<Range<usize>> {
+        match &self.field {
+            Field::Column { index, delimiter } => {
+                csv_fields(line, *delimiter).into_iter().nth((*index)?)
+            }
+            Field::Json(keys) => json_field(line, keys),
+        }
+    }
+}
+
+impl Matcher for FieldMatcher<'_> {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        let field = self.locate(haystack)?;
+        if start > field.end {
+            return None;
+        }
+        let found = self
+            .inner
+            .find_at(&haystack[field.clone()], start.saturating_sub(field.start))?;
+        // A search starting past the field can't find anything in it.
+        if start > field.start + found.start {
+            return None;
+        }
+        Some(field.start + found.start..field.start + found.end)
+    }
+
+    fn captures_at(&self, haystack: &str, start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        let field = self.locate(haystack)?;
+        if start > field.end {
+            return None;
+        }
+        let groups = self
+            .inner
+            .captures_at(&haystack[field.clone()], start.saturating_sub(field.start))?;
+        Some(
+            groups
+                .into_iter()
+                .map(|group| group.map(|g| field.start + g.start..field.start + g.end))
+                .collect(),
+        )
+    }
+
+    fn distance(&self, haystack: &str, found: Range<usize>) -> Option<usize> {
+        let field = self.locate(haystack)?;
+        self.inner.distance(
+            &haystack[field.clone()],
+            found.start - field.start..found.end - field.start,
+        )
+    }
+}
+
+// Returns the range of every field in a CSV or TSV record. Quoted fields are
+// returned without their quotes, but doubled quotes inside them are kept.
+fn csv_fields(line: &str, delimiter: u8) -> Vec<Range<usize>> {
+    let bytes = line.as_bytes();
+    let mut fields = Vec::new();
+    let mut i = 0;
+    loop {
+        if bytes.get(i) == Some(&b'"') {
+            let start = i + 1;
+            let mut end = start;
+            while end < bytes.len() {
+                match (bytes[end], bytes.get(end + 1)) {
+                    (b'"', Some(&b'"')) => end += 2,
+                    (b'"', _) => break,
+                    _ => end += 1,
+                }
+            }
+            let end = end.min(bytes.len());
+            fields.push(start..end);
+            // Anything between the closing quote and the delimiter is ignored.
+            i = bytes[end..]
+                .iter()
+                .position(|&b| b == delimiter)
+                .map_or(bytes.len(), |p| end + p);
+        } else {
+            let end = bytes[i..]
+                .iter()
+                .position(|&b| b == delimiter)
+                .map_or(bytes.len(), |p| i + p);
+            fields.push(i..end);
+            i = end;
+        }
+        if i >= bytes.len() {
+            return fields;
+        }
+        // Step over the delimiter.
+        i += 1;
+    }
+}
+
+fn unquote(field: &str) -> Cow<'_, str> {
+    if field.contains("\"\"") {
+        Cow::Owned(field.replace("\"\"", "\""))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+// Returns the range of the value at the keys in a JSON object, or None if the
+// line isn't an object or doesn't have the keys.
+fn json_field(line: &str, keys: &[&str]) -> Option<Range<usize>> {
+    let bytes = line.as_bytes();
+    let mut value = skip_whitespace(bytes, 0)..line.len();
+    for key in keys {
+        value = object_value(line, value.start, key)?;
+    }
+    if bytes[value.start] == b'"' {
+        Some(value.start + 1..value.end - 1)
+    } else {
+        Some(value)
+    }
+}
+
+// Returns the range of the value of the key in the object starting at pos.
+fn object_value(line: &str, mut pos: usize, key: &str) -> Option<Range<usize>> {
+    let bytes = line.as_bytes();
+    if bytes.get(pos) != Some(&b'{') {
+        return None;
+    }
+    pos = skip_whitespace(bytes, pos + 1);
+    if bytes.get(pos) == Some(&b'}') {
+        return None;
+    }
+    loop {
+        let key_end = string_end(bytes, pos)?;
+        let name = &line[pos + 1..key_end - 1];
+        pos = skip_whitespace(bytes, key_end);
+        if bytes.get(pos) != Some(&b':') {
+            return None;
+        }
+        let start = skip_whitespace(bytes, pos + 1);
+        let end = value_end(bytes, start)?;
+        if name == key {
+            return Some(start..end);
+        }
+        pos = skip_whitespace(bytes, end);
+        if bytes.get(pos) != Some(&b',') {
+            return None;
+        }
+        pos = skip_whitespace(bytes, pos + 1);
+    }
+}
+
+fn skip_whitespace(bytes: &[u8], pos: usize) -> usize {
+    pos + bytes[pos.min(bytes.len())..]
+        .iter()
+        .take_while(|b| b.is_ascii_whitespace())
+        .count()
+}
+
+// Returns the end of the string starting at pos, just past its closing quote.
+fn string_end(bytes: &[u8], pos: usize) -> Option<usize> {
+    if bytes.get(pos) != Some(&b'"') {
+        return None;
+    }
+    let mut i = pos + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' => return Some(i + 1),
+            _ => i += 1,
+        }
+    }
+    None
+}
+
+// Returns the end of the value starting at pos. Objects and arrays are
+// skipped as a whole, minding the brackets inside strings.
+fn value_end(bytes: &[u8], pos: usize) -> Option<usize> {
+    match bytes.get(pos)? {
+        b'"' => string_end(bytes, pos),
+        b'{' | b'[' => {
+            let mut depth = 0;
+            let mut i = pos;
+            while i < bytes.len() {
+                match bytes[i] {
+                    b'"' => {
+                        i = string_end(bytes, i)?;
+                        continue;
+                    }
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(i + 1);
+                        }
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            None
+        }
+        _ => {
+            let len = bytes[pos..]
+                .iter()
+                .take_while(|b| !matches!(b, b',' | b'}' | b']') && !b.is_ascii_whitespace())
+                .count();
+            Some(pos + len)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{find_iter, Regex, Substring};
+
+    #[test]
+    fn csv() {
+        let line = r#"1,"Dickinson, Emily","I'm ""nobody""",,x"#;
+        let fields: Vec<&str> = csv_fields(line, b',')
+            .into_iter()
+            .map(|range| &line[range])
+            .collect();
+        assert_eq!(
+            vec!["1", "Dickinson, Emily", r#"I'm ""nobody"""#, "", "x"],
+            fields
+        );
+        assert_eq!(vec![0..0], csv_fields("", b','));
+        assert_eq!(vec![0..1, 2..2], csv_fields("a,", b','));
+    }
+
+    #[test]
+    fn column_by_name_or_number() {
+        let header = r#"id,author,"first ""line""""#;
+        let line = r#"7,Dickinson,"I'm nobody, who are you?""#;
+        let inner = Substring::new("o", false);
+
+        let matcher = FieldMatcher::new(&inner, InputFormat::Csv, "author", header).unwrap();
+        assert_eq!(vec![9..10], find_iter(&matcher, line).collect::<Vec<_>>());
+        let matcher =
+            FieldMatcher::new(&inner, InputFormat::Csv, "first \"line\"", header).unwrap();
+        assert_eq!(Some(18..19), matcher.find_at(line, 0));
+        let matcher = FieldMatcher::new(&inner, InputFormat::Csv, "2", header).unwrap();
+        assert_eq!(Some(9..10), matcher.find_at(line, 0));
+        let error = FieldMatcher::new(&inner, InputFormat::Csv, "missing", header)
+            .err()
+            .unwrap();
+        assert_eq!("no column `missing`", error.to_string());
+        // An empty file doesn't have a header to look the column up in.
+        let matcher = FieldMatcher::new(&inner, InputFormat::Csv, "missing", "").unwrap();
+        assert!(!matcher.is_match(line));
+    }
+
+    #[test]
+    fn anchors_apply_to_the_field() {
+        let inner = Regex::new("^D.*n$", false).unwrap();
+        let matcher = FieldMatcher::new(&inner, InputFormat::Tsv, "author", "id\tauthor").unwrap();
+        assert_eq!(Some(2..11), matcher.find_at("7\tDickinson", 0));
+        assert!(!matcher.is_match("Dickinson\tDickinsons"));
+    }
+
+    #[test]
+    fn json_keys() {
+        let line = r#"{"level": "warn", "user": {"name": "ferris", "tags": ["a}", 1]}, "n": 42}"#;
+        assert_eq!(Some(11..15), json_field(line, &["level"]));
+        assert_eq!(
+            Some("ferris"),
+            json_field(line, &["user", "name"]).map(|r| &line[r])
+        );
+        assert_eq!(
+            Some(r#"["a}", 1]"#),
+            json_field(line, &["user", "tags"]).map(|r| &line[r])
+        );
+        assert_eq!(Some("42"), json_field(line, &["n"]).map(|r| &line[r]));
+        assert_eq!(None, json_field(line, &["name"]));
+        assert_eq!(None, json_field("not json", &["level"]));
+
+        let inner = Substring::new("er", false);
+        let matcher = FieldMatcher::new(&inner, InputFormat::Jsonl, "user.name", "").unwrap();
+        assert_eq!(Some(37..39), matcher.find_at(line, 0));
+        assert_eq!(None, matcher.find_at(line, 39));
+    }
+}
@@ -0,0 +1,227 @@
+use std::{collections::HashMap, ops::Range};
+
+use crate::matcher::{fold_case, Matcher};
+
+// Finds the query with up to `max_distance` edits (chars inserted, deleted or
+// substituted) using Myers' bit-parallel algorithm. A column of the edit
+// distance table is kept as bit vectors of the +1/-1 differences between its
+// cells, so each char of the haystack is handled with a handful of word
+// operations instead of a loop over the query.
+pub struct Fuzzy {
+    query: Vec<char>,
+    max_distance: usize,
+    ignore_case: bool,
+    // For every char in the query, the bits of the positions it is at.
+    peq: HashMap<char, u64>,
+    // The same for the query reversed, which is used to find where a match
+    // starts once its end is known.
+    peq_rev: HashMap<char, u64>,
+}
+
+impl Fuzzy {
+    /// The longest query that fits in the bit vectors.
+    pub const MAX_LEN: usize = 64;
+
+    /// Create a new fuzzy matcher.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the query is longer than `MAX_LEN`
+    /// chars.
+    pub fn new(query: &str, max_distance: usize, ignore_case: bool) -> Fuzzy {
+        let query: Vec<char> = query.chars().map(|c| key(c, ignore_case)).collect();
+        assert!(query.len() <= Fuzzy::MAX_LEN);
+
+        let mut peq = HashMap::new();
+        let mut peq_rev = HashMap::new();
+        for (i, &c) in query.iter().enumerate() {
+            *peq.entry(c).or_insert(0) |= 1 << i;
+            *peq_rev.entry(c).or_insert(0) |= 1 << (query.len() - 1 - i);
+        }
+        Fuzzy {
+            query,
+            max_distance,
+            ignore_case,
+            peq,
+            peq_rev,
+        }
+    }
+
+    // Returns the edit distance between the query and `text`.
+    fn distance_to(&self, text: &str) -> usize {
+        let mut column: Vec<usize> = (0..=self.query.len()).collect();
+        for (j, c) in text.chars().enumerate() {
+            let c = key(c, self.ignore_case);
+            let mut diagonal = column[0];
+            column[0] = j + 1;
+            for (i, &q) in self.query.iter().enumerate() {
+                let substitute = diagonal + usize::from(q != c);
+                diagonal = column[i + 1];
+                column[i + 1] = substitute.min(column[i] + 1).min(diagonal + 1);
+            }
+        }
+        column[self.query.len()]
+    }
+}
+
+impl Matcher for Fuzzy {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        let len = self.query.len();
+        if len <= self.max_distance {
+            // Deleting the whole query is allowed, so it matches anywhere.
+            return Some(start..start);
+        }
+
+        // The end of the match is the first place the distance is small
+        // enough, moved along for as long as the distance keeps dropping.
+        let mut search = Myers::new(len, false);
+        let mut found: Option<(usize, usize)> = None;
+        for (i, c) in haystack[start..].char_indices() {
+            let score = search.step(self.peq.get(&key(c, self.ignore_case)).copied());
+            let end = start + i + c.len_utf8();
+            match found {
+                Some((_, best)) if score < best => found = Some((end, score)),
+                Some(_) => break,
+                None if score <= self.max_distance => found = Some((end, score)),
+                None => {}
+            }
+        }
+        let (end, _) = found?;
+
+        // Running the reversed query backwards from the end gives the distance
+        // for every start, and the closest start with the lowest distance is
+        // used. A match can't be longer than the query plus the edits.
+        let mut search = Myers::new(len, true);
+        let mut begin = end;
+        let mut best = len;
+        for (i, c) in haystack[start..end]
+            .char_indices()
+            .rev()
+            .take(len + self.max_distance)
+        {
+            let score = search.step(self.peq_rev.get(&key(c, self.ignore_case)).copied());
+            if score < best {
+                best = score;
+                begin = start + i;
+            }
+        }
+        Some(begin..end)
+    }
+
+    fn distance(&self, haystack: &str, found: Range<usize>) -> Option<usize> {
+        Some(self.distance_to(&haystack[found]))
+    }
+}
+
+// One column of the edit distance table, i.e. the distances from every prefix
+// of the query to the text seen so far. Only the last cell, the distance to
+// the whole query, is kept as a number.
+struct Myers {
+    // The bits set where a cell is one more (pv) or one less (mv) than the
+    // cell above it.
+    pv: u64,
+    mv: u64,
+    score: usize,
+    high: u64,
+    // When anchored the match has to start at the first char, otherwise it
+    // can start anywhere for free.
+    anchored: bool,
+}
+
+impl Myers {
+    fn new(len: usize, anchored: bool) -> Myers {
+        Myers {
+            pv: !0,
+            mv: 0,
+            score: len,
+            high: 1 << (len - 1),
+            anchored,
+        }
+    }
+
+    fn step(&mut self, eq: Option<u64>) -> usize {
+        let eq = eq.unwrap_or(0);
+        let xv = eq | self.mv;
+        let xh = ((eq & self.pv).wrapping_add(self.pv) ^ self.pv) | eq;
+        let mut ph = self.mv | !(xh | self.pv);
+        let mut mh = self.pv & xh;
+        if ph & self.high != 0 {
+            self.score += 1;
+        } else if mh & self.high != 0 {
+            self.score -= 1;
+        }
+        ph <<= 1;
+        mh <<= 1;
+        if self.anchored {
+            // The row above the table counts up by one for every char, rather
+            // than being all zeros.
+            ph |= 1;
+        }
+        self.pv = mh | !(xv | ph);
+        self.mv = ph & xv;
+        self.score
+    }
+}
+
+// Chars are compared one to one, so when ignoring case a char that folds to
+// several chars, like 'ß', is only lowercased. That still makes 'ẞ' and 'ß'
+// equal, but not 'ß' and "ss".
+fn key(c: char, ignore_case: bool) -> char {
+    if !ignore_case {
+        return c;
+    }
+    let mut folded = fold_case(c);
+    match (folded.next(), folded.next()) {
+        (Some(folded), None) => folded,
+        _ => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'a>(query: &str, max_distance: usize, haystack: &'a str) -> Option<(&'a str, usize)> {
+        let matcher = Fuzzy::new(query, max_distance, false);
+        let found = matcher.find_at(haystack, 0)?;
+        let distance = matcher.distance(haystack, found.clone()).unwrap();
+        Some((&haystack[found], distance))
+    }
+
+    #[test]
+    fn approximate() {
+        assert_eq!(
+            Some(("productive", 0)),
+            find("productive", 1, "safe, fast, productive.")
+        );
+        assert_eq!(
+            Some(("prodctive", 1)),
+            find("productive", 1, "safe, fast, prodctive.")
+        );
+        assert_eq!(
+            Some(("prodcutive", 2)),
+            find("productive", 2, "safe, fast, prodcutive.")
+        );
+        assert_eq!(None, find("productive", 1, "safe, fast, prodcutive."));
+        assert_eq!(
+            Some(("nobdy", 1)),
+            find("nobody", 1, "I'm nobdy! Who are you?")
+        );
+        assert_eq!(Some(("", 2)), find("ab", 2, "xyz"));
+    }
+
+    #[test]
+    fn ignore_case_and_offsets() {
+        let matcher = Fuzzy::new("straße", 1, true);
+        assert_eq!(Some(5..13), matcher.find_at("Die  STRAẞE!", 0));
+        assert_eq!(Some(0), matcher.distance("Die  STRAẞE!", 5..13));
+        assert!(!Fuzzy::new("rust", 1, false).is_match("RUST"));
+    }
+
+    #[test]
+    fn distance() {
+        let matcher = Fuzzy::new("kitten", 3, false);
+        assert_eq!(Some(3), matcher.distance("sitting", 0..7));
+        assert_eq!(Some(6), matcher.distance("", 0..0));
+    }
+}
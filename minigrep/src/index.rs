@@ -0,0 +1,419 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process, str,
+    time::UNIX_EPOCH,
+};
+
+use crate::{decompress, matcher::fold_case, walk, Walker};
+
+/// The name of the index file that `minigrep index` writes in the directory it
+/// indexes.
+pub const INDEX_FILE: &str = ".minigrep-index";
+
+// The first line of the index file. The number goes up whenever the format
+// changes, so an old index is rebuilt rather than misread.
+const HEADER: &str = "minigrep index 1";
+
+// Files are read this many bytes at a time when indexing, so a big file
+// doesn't have to fit in memory.
+const CHUNK_LEN: usize = 64 * 1024;
+
+// A trigram index records, for every file, each run of three bytes in it. A
+// file can only contain the query if it contains every trigram of the query,
+// so most files can be skipped without reading them.
+//
+// The trigrams are taken from the case folded text, which makes the index
+// work for case-insensitive searches too: if a line contains the query in any
+// case, the folded line contains the folded query.
+pub struct Index {
+    // Keyed by the path relative to the indexed directory, with / between
+    // the components.
+    files: HashMap<String, Entry>,
+}
+
+struct Entry {
+    // The modification time in nanoseconds and the length of the file when
+    // it was indexed. If either changed the entry can't be trusted.
+    modified: u128,
+    len: u64,
+    // Sorted so looking up a trigram is a binary search.
+    trigrams: Vec<u32>,
+}
+
+/// What `Index::update` did.
+pub struct IndexStats {
+    pub files: usize,
+    /// The files that were new or changed since the last update, and so had
+    /// to be read.
+    pub updated: usize,
+}
+
+impl Index {
+    /// Loads the index of a directory, or returns `None` if it hasn't been
+    /// indexed.
+    pub fn load(dir: &Path) -> io::Result<Option<Index>> {
+        let file = match File::open(dir.join(INDEX_FILE)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid minigrep index");
+
+        let mut lines = BufReader::new(file).lines();
+        if lines.next().transpose()?.as_deref() != Some(HEADER) {
+            return Err(invalid());
+        }
+        let mut files = HashMap::new();
+        for line in lines {
+            let line = line?;
+            let mut fields = line.splitn(4, ' ');
+            let (Some(modified), Some(len), Some(trigrams), Some(path)) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid());
+            };
+            let entry = Entry {
+                modified: modified.parse().map_err(|_| invalid())?,
+                len: len.parse().map_err(|_| invalid())?,
+                trigrams: parse_trigrams(trigrams).ok_or_else(invalid)?,
+            };
+            files.insert(path.to_string(), entry);
+        }
+        Ok(Some(Index { files }))
+    }
+
+    /// Indexes the files in a directory and writes the index into it. Files
+    /// that haven't changed since the last update are not read again.
+    pub fn update(dir: &Path, walker: &Walker) -> io::Result<IndexStats> {
+        // A broken or outdated index is rebuilt from scratch.
+        let mut old = Index::load(dir)
+            .unwrap_or(None)
+            .map_or_else(HashMap::new, |index| index.files);
+        let mut files = Vec::new();
+        let mut updated = 0;
+
+        // Directories that can't be read are left out of the index, so
+        // searching them reports the error.
+        let (paths, _) = walker.walk(&[dir.to_string_lossy().into_owned()]);
+        for path in paths {
+            // Files whose names can't be written on one line are left out, so
+            // they are always searched.
+            let Some(name) = relative_name(dir, &path).filter(|name| !name.contains('\n')) else {
+                continue;
+            };
+            // A file that was removed since the walk is left out, like one
+            // that can't be read.
+            let Ok(metadata) = fs::metadata(&path) else {
+                continue;
+            };
+            let (modified, len) = (modified(&metadata), metadata.len());
+
+            let entry = match old.remove(&name) {
+                Some(entry) if entry.modified == modified && entry.len == len => entry,
+                _ => {
+                    updated += 1;
+                    // Compressed files are indexed by what they decompress
+                    // to. A file that can't be read is left out of the index,
+                    // so searching it reports the error.
+                    let read = decompress::open(&path, false)
+                        .and_then(|mut reader| file_trigrams(&mut reader, CHUNK_LEN));
+                    let Ok(trigrams) = read else {
+                        continue;
+                    };
+                    Entry {
+                        modified,
+                        len,
+                        trigrams,
+                    }
+                }
+            };
+            files.push((name, entry));
+        }
+
+        let stats = IndexStats {
+            files: files.len(),
+            updated,
+        };
+        write_index(dir, &files)?;
+        Ok(stats)
+    }
+
+    // Returns false only if the index is sure the file has none of the
+    // queries. Each query is a list of trigrams that must all be present.
+    fn may_match(&self, name: &str, path: &Path, queries: &[Vec<u32>]) -> bool {
+        let Some(entry) = self.files.get(name) else {
+            return true;
+        };
+        // A file that changed since it was indexed has to be searched.
+        match fs::metadata(path) {
+            Ok(metadata)
+                if modified(&metadata) == entry.modified && metadata.len() == entry.len => {}
+            _ => return true,
+        }
+        queries.iter().any(|query| {
+            query
+                .iter()
+                .all(|trigram| entry.trigrams.binary_search(trigram).is_ok())
+        })
+    }
+}
+
+/// Returns the trigrams a file must have to contain the query, or `None` if
+/// the query is too short to have any.
+pub fn query_trigrams(query: &str) -> Option<Vec<u32>> {
+    let mut trigrams = HashSet::new();
+    add_trigrams(&mut trigrams, &fold(query));
+    if trigrams.is_empty() {
+        None
+    } else {
+        Some(sorted(trigrams))
+    }
+}
+
+/// Removes the files that can't match from the files to search, using the
+/// indexes of the directories being searched. Each query is the trigrams from
+/// `query_trigrams` of one pattern.
+pub fn filter(
+    paths: &[String],
+    files: Vec<PathBuf>,
+    queries: &[Vec<u32>],
+) -> io::Result<Vec<PathBuf>> {
+    let mut indexes = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_dir() {
+            if let Some(index) = Index::load(path)? {
+                indexes.push((path, index));
+            }
+        }
+    }
+    if indexes.is_empty() {
+        return Ok(files);
+    }
+
+    Ok(files
+        .into_iter()
+        .filter(|file| {
+            indexes
+                .iter()
+                .all(|(dir, index)| match relative_name(dir, file) {
+                    Some(name) => index.may_match(&name, file, queries),
+                    None => true,
+                })
+        })
+        .collect())
+}
+
+fn relative_name(dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(dir).ok()?;
+    let components: Option<Vec<&str>> = relative
+        .components()
+        .map(|component| component.as_os_str().to_str())
+        .collect();
+    Some(components?.join("/"))
+}
+
+fn modified(metadata: &fs::Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos())
+}
+
+fn fold(text: &str) -> Vec<u8> {
+    text.chars()
+        .flat_map(fold_case)
+        .collect::<String>()
+        .into_bytes()
+}
+
+// Reads the file a chunk at a time, so only its trigrams are held in memory
+// rather than the whole file. The last two folded bytes of each chunk are
+// carried over to the next, so the trigrams that span chunks are still found.
+// Binary files get no trigrams, since they are never searched anyway, which
+// is told from the first chunk.
+fn file_trigrams(reader: &mut impl Read, chunk_len: usize) -> io::Result<Vec<u32>> {
+    let mut trigrams = HashSet::new();
+    let mut buf = Vec::with_capacity(chunk_len);
+    let mut folded = Vec::new();
+    let mut first = true;
+    loop {
+        let read = reader
+            .by_ref()
+            .take(chunk_len as u64)
+            .read_to_end(&mut buf)?;
+        if first && walk::is_binary(&buf) {
+            return Ok(Vec::new());
+        }
+        first = false;
+
+        let (text, left) = decode(&buf, read == 0);
+        folded.extend(fold(&text));
+        add_trigrams(&mut trigrams, &folded);
+        folded.drain(..folded.len().saturating_sub(2));
+        buf.drain(..buf.len() - left);
+        if read == 0 {
+            return Ok(sorted(trigrams));
+        }
+    }
+}
+
+// Decodes the bytes the way String::from_utf8_lossy does, except that a char
+// cut off at the end is left for the next chunk unless this is the last one.
+// Returns the text and the number of bytes left over.
+fn decode(mut bytes: &[u8], last: bool) -> (String, usize) {
+    let mut text = String::new();
+    loop {
+        let e = match str::from_utf8(bytes) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return (text, 0);
+            }
+            Err(e) => e,
+        };
+        let (valid, rest) = bytes.split_at(e.valid_up_to());
+        text.push_str(str::from_utf8(valid).unwrap());
+        match e.error_len() {
+            None if !last => return (text, rest.len()),
+            len => {
+                text.push(char::REPLACEMENT_CHARACTER);
+                bytes = &rest[len.unwrap_or(rest.len())..];
+            }
+        }
+    }
+}
+
+fn add_trigrams(trigrams: &mut HashSet<u32>, bytes: &[u8]) {
+    trigrams.extend(
+        bytes
+            .windows(3)
+            .map(|w| u32::from_be_bytes([0, w[0], w[1], w[2]])),
+    );
+}
+
+fn sorted(trigrams: HashSet<u32>) -> Vec<u32> {
+    let mut trigrams: Vec<u32> = trigrams.into_iter().collect();
+    trigrams.sort_unstable();
+    trigrams
+}
+
+// The trigrams are written as six hex digits each, or - if there are none.
+fn parse_trigrams(field: &str) -> Option<Vec<u32>> {
+    if field == "-" {
+        return Some(Vec::new());
+    }
+    if !field.len().is_multiple_of(6) || !field.is_ascii() {
+        return None;
+    }
+    (0..field.len())
+        .step_by(6)
+        .map(|i| u32::from_str_radix(&field[i..i + 6], 16).ok())
+        .collect()
+}
+
+// The index is written to a temporary file and renamed into place, so a
+// search running at the same time never sees half of it.
+fn write_index(dir: &Path, files: &[(String, Entry)]) -> io::Result<()> {
+    let path = dir.join(INDEX_FILE);
+    let temp = dir.join(format!("{INDEX_FILE}.{}", process::id()));
+
+    let result = (|| {
+        let mut out = BufWriter::new(File::create(&temp)?);
+        writeln!(out, "{HEADER}")?;
+        for (name, entry) in files {
+            write!(out, "{} {} ", entry.modified, entry.len)?;
+            if entry.trigrams.is_empty() {
+                write!(out, "-")?;
+            }
+            for trigram in &entry.trigrams {
+                write!(out, "{trigram:06x}")?;
+            }
+            writeln!(out, " {name}")?;
+        }
+        out.into_inner()?.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn query_trigrams_are_folded() {
+        assert_eq!(None, query_trigrams("ab"));
+        assert_eq!(query_trigrams("RUST"), query_trigrams("rust"));
+        assert_eq!(query_trigrams("STRASSE"), query_trigrams("straße"));
+        assert_eq!(Some(vec![0x727573, 0x757374]), query_trigrams("Rust"));
+    }
+
+    #[test]
+    fn trigrams_across_chunks() {
+        let mut contents = "Größe \u{1f980} nobody".as_bytes().to_vec();
+        // A byte that isn't UTF-8, and a char that is cut off.
+        contents.extend(b"\xff fast \xe2\x82 STRASSE \xe2\x82");
+        let mut expected = HashSet::new();
+        add_trigrams(&mut expected, &fold(&String::from_utf8_lossy(&contents)));
+        let expected = sorted(expected);
+
+        // Chunks this short end everywhere, including in the middle of a char.
+        for chunk_len in 1..8 {
+            let trigrams = file_trigrams(&mut contents.as_slice(), chunk_len).unwrap();
+            assert_eq!(expected, trigrams, "{chunk_len}");
+        }
+        let trigrams = file_trigrams(&mut contents.as_slice(), CHUNK_LEN).unwrap();
+        assert_eq!(expected, trigrams);
+        assert!(file_trigrams(&mut "".as_bytes(), CHUNK_LEN)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn update_and_filter() {
+        let dir = TempDir::new("index");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("poem.txt"), "I'm nobody! Who are you?").unwrap();
+        fs::write(dir.join("sub/rust.txt"), "Rust:\nsafe, fast, productive.").unwrap();
+        fs::write(dir.join("binary"), "nobody\0").unwrap();
+
+        let walker = Walker {
+            hidden: true,
+            ignore: true,
+        };
+        let stats = Index::update(&dir, &walker).unwrap();
+        assert_eq!((3, 3), (stats.files, stats.updated));
+        let stats = Index::update(&dir, &walker).unwrap();
+        assert_eq!((3, 0), (stats.files, stats.updated));
+
+        let paths = [dir.to_string_lossy().into_owned()];
+        let search = |queries: &[&str]| -> Vec<String> {
+            let queries: Vec<Vec<u32>> = queries
+                .iter()
+                .map(|query| query_trigrams(query).unwrap())
+                .collect();
+            let (files, errors) = walker.walk(&paths);
+            assert!(errors.is_empty());
+            filter(&paths, files, &queries)
+                .unwrap()
+                .iter()
+                .map(|file| relative_name(&dir, file).unwrap())
+                .collect()
+        };
+        assert_eq!(vec!["poem.txt"], search(&["NOBODY"]));
+        assert_eq!(vec!["poem.txt", "sub/rust.txt"], search(&["who", "fast"]));
+        assert_eq!(Vec::<String>::new(), search(&["frog"]));
+
+        // A file that changed since it was indexed is always searched.
+        fs::write(dir.join("sub/rust.txt"), "Rust: frogs").unwrap();
+        assert_eq!(vec!["sub/rust.txt"], search(&["frog"]));
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+
+// Just enough JSON to write the --json output without pulling in a crate for
+// it. Strings are the only values that need any care.
+
+/// Writes `s` as a quoted JSON string, escaping the chars JSON doesn't allow
+/// inside of a string.
+pub fn write_string(out: &mut impl Write, s: &str) -> io::Result<()> {
+    write!(out, "\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            // Other control chars don't have a short escape.
+            c if (c as u32) < 0x20 => "",
+            _ => continue,
+        };
+        write!(out, "{}", &s[start..i])?;
+        if escaped.is_empty() {
+            write!(out, "\\u{:04x}", c as u32)?;
+        } else {
+            write!(out, "{escaped}")?;
+        }
+        start = i + c.len_utf8();
+    }
+    write!(out, "{}\"", &s[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes() {
+        let mut out = Vec::new();
+        write_string(&mut out, "say \"hi\"\n\t\\ \u{1} ß").unwrap();
+
+        assert_eq!(
+            r#""say \"hi\"\n\t\\ \u0001 ß""#,
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
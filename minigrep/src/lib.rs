@@ -1,85 +1,692 @@
+mod aho_corasick;
+mod cli;
+mod decompress;
+mod error;
+mod field;
+mod fuzzy;
+mod index;
+mod json;
+mod matcher;
+mod pool;
+mod printer;
+mod regex;
+mod replace;
+#[cfg(test)]
+mod test_util;
+mod tui;
+mod walk;
+
 use std::{
-    env::{self, Args},
-    error::Error,
-    fs,
+    borrow::Cow,
+    collections::HashMap,
+    io::{self, BufRead, Read, Write},
+    ops::Range,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Instant,
 };
 
+use pool::ThreadPool;
+
+pub use aho_corasick::AhoCorasick;
+pub use cli::{Command, ConfigError, USAGE, VERSION};
+pub use error::{MinigrepError, Outcome};
+pub use field::{FieldMatcher, InputFormat};
+pub use fuzzy::Fuzzy;
+pub use index::{Index, IndexStats, INDEX_FILE};
+pub use matcher::{find_iter, fold_case, has_uppercase, FindIter, Matcher, Substring};
+pub use printer::{ColorChoice, FileSummary, OutputMode, Printer};
+pub use regex::{Regex, RegexError};
+pub use replace::replace_all;
+pub use tui::interactive;
+pub use walk::Walker;
+
+#[derive(Default)]
 pub struct Config {
-    pub query: String,
-    pub file_path: String,
+    // The patterns to search for. A line matches if any of them matches.
+    pub patterns: Vec<String>,
+    // Files and directories to search. Directories are searched recursively.
+    pub paths: Vec<String>,
+    // Defaults to whether the IGNORE_CASE environment variable is set.
     pub ignore_case: bool,
+    // Ignore case unless a pattern has an uppercase letter in it.
+    pub smart_case: bool,
+    // Compile the patterns as regular expressions instead of plain strings.
+    pub regex: bool,
+    // Match the query with up to this many chars inserted, deleted or
+    // substituted.
+    pub fuzzy: Option<usize>,
+    // Also search hidden files and directories when walking directories.
+    pub hidden: bool,
+    // Don't skip files matched by .gitignore and .ignore rules.
+    pub no_ignore: bool,
+    // Also decompress standard input, and the files named like a compressed
+    // file that don't start like one.
+    pub search_zip: bool,
+    // Search each file as a whole, so matches can span lines.
+    pub multiline: bool,
+    // How the lines are split into fields, for CSV, TSV and JSON Lines.
+    pub format: InputFormat,
+    // Only match within this column or JSON key of each record.
+    pub field: Option<String>,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    // Number of context lines to print before and after each match.
+    pub before_context: usize,
+    pub after_context: usize,
+    // Select the lines that don't match instead.
+    pub invert_match: bool,
+    // Stop searching a file after this many matching lines.
+    pub max_count: Option<usize>,
+    pub mode: OutputMode,
+    // Number of files to search at the same time. Defaults to the number of
+    // CPUs.
+    pub threads: Option<usize>,
+    // Print JSON Lines records instead of text.
+    pub json: bool,
+    pub color: ColorChoice,
+    // Print the number of matches in each file and totals at the end.
+    pub stats: bool,
+    // Browse the results in a terminal UI instead of printing them.
+    pub interactive: bool,
+    // Replace the matches with this text, printing a diff of the changes.
+    pub replace: Option<String>,
+    // Write the replacements back to the files instead of printing a diff.
+    pub write: bool,
 }
 
 impl Config {
-    // New handles the parsing of the command line arguments into data.
-    //
-    // We originally had to use clone on the args because we didn't have ownership
-    // of the args variable, but we can take ownership of the iterator.
-    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
-        args.next();
-
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("no query string"),
-        };
+    /// Builds the matcher for the patterns, which fails if a pattern is not
+    /// a valid regular expression.
+    pub fn matcher(&self) -> Result<Box<dyn Matcher>, RegexError> {
+        let patterns = self.patterns();
+        let ignore_case = self.ignore_case
+            || (self.smart_case
+                && !patterns
+                    .iter()
+                    .any(|pattern| has_uppercase(pattern, self.regex)));
+
+        // The command line parser makes sure there is at most one query. An
+        // interactive search doesn't have one until it is typed in.
+        if let (Some(max_distance), [query]) = (self.fuzzy, &patterns[..]) {
+            return Ok(Box::new(Fuzzy::new(query, max_distance, ignore_case)));
+        }
+        // An empty pattern file gives no patterns, and nothing matches. The
+        // automaton for no patterns has no matches to find, while an empty
+        // regex would match every line.
+        if self.regex && !patterns.is_empty() {
+            if let [pattern] = &patterns[..] {
+                return Ok(Box::new(Regex::new(pattern, ignore_case)?));
+            }
+            // Several regexes are joined into one alternation. Each one is
+            // compiled on its own first, so an error points at the pattern
+            // the user wrote rather than the joined one.
+            for pattern in &patterns {
+                Regex::new(pattern, ignore_case)?;
+            }
+            let joined: Vec<String> = patterns
+                .iter()
+                .map(|pattern| format!("(?:{pattern})"))
+                .collect();
+            Ok(Box::new(Regex::new(&joined.join("|"), ignore_case)?))
+        } else if let [query] = &patterns[..] {
+            Ok(Box::new(Substring::new(query, ignore_case)))
+        } else {
+            Ok(Box::new(AhoCorasick::new(&patterns, ignore_case)))
+        }
+    }
+
+    // In multiline mode a plain query can have \n in it to match a line
+    // ending, since a newline is awkward to type in an argument. Regexes
+    // already understand \n.
+    fn patterns(&self) -> Vec<Cow<'_, str>> {
+        self.patterns
+            .iter()
+            .map(|pattern| {
+                if self.multiline && !self.regex && pattern.contains("\\n") {
+                    Cow::Owned(pattern.replace("\\n", "\n"))
+                } else {
+                    Cow::Borrowed(pattern.as_str())
+                }
+            })
+            .collect()
+    }
+
+    // The trigrams to look up in an index for each pattern. The index only
+    // knows which strings are in a file, so it can't help with regexes,
+    // fuzzy matches or lines that don't match. Counts are printed for every
+    // file, even the ones without a match, and --files-without-match prints
+    // exactly the files the index would skip, so no file can be skipped.
+    fn index_queries(&self) -> Option<Vec<Vec<u32>>> {
+        // The index only decompressed the files that start like a compressed
+        // file.
+        if self.regex
+            || self.search_zip
+            || self.fuzzy.is_some()
+            || self.invert_match
+            || matches!(self.mode, OutputMode::Count | OutputMode::FilesWithoutMatch)
+        {
+            return None;
+        }
+        self.patterns()
+            .iter()
+            .map(|pattern| index::query_trigrams(pattern))
+            .collect()
+    }
+}
+
+/// Builds or updates the trigram index of each directory.
+pub fn build_index(dirs: &[String], walker: &Walker) -> Result<(), MinigrepError> {
+    for dir in dirs {
+        let stats =
+            Index::update(Path::new(dir), walker).map_err(|source| MinigrepError::File {
+                path: PathBuf::from(dir),
+                source,
+            })?;
+        println!(
+            "{dir}: indexed {} files, {} new or changed",
+            stats.files, stats.updated
+        );
+    }
+    Ok(())
+}
+
+// An error is returned when the search can't go on, like for an invalid
+// pattern or when the output can't be written. Files that can't be read are
+// reported as they come up and the rest are still searched, which the
+// Outcome counts so the exit status can tell.
+pub fn run(config: Config) -> Result<Outcome, MinigrepError> {
+    let start = Instant::now();
+    let matcher = config.matcher()?;
+    let walker = Walker {
+        hidden: config.hidden,
+        ignore: !config.no_ignore,
+    };
+    let (mut files, errors) = walker.walk(&config.paths);
+    let mut failed = errors.len();
+    errors.iter().for_each(report);
+    // Directories that were indexed with `minigrep index` only have the files
+    // that could contain the query searched.
+    if let Some(queries) = config.index_queries() {
+        files = index::filter(&config.paths, files, &queries)?;
+    }
+
+    if let Some(replacement) = &config.replace {
+        let mut out = io::stdout().lock();
+        let mut matched = false;
+        for path in files {
+            match replace::replace_file(
+                &mut out,
+                &path,
+                matcher.as_ref(),
+                replacement,
+                config.write,
+            ) {
+                Ok(changed) => matched |= changed,
+                Err(e) => skip(error::search_error(e, &path), &mut failed)?,
+            }
+        }
+        return Ok(Outcome { matched, failed });
+    }
+
+    // The printer is shared with the threads when searching in parallel.
+    let printer = Arc::new(Printer {
+        // Like grep, only prefix the lines with the file name when more than
+        // one file could be searched.
+        with_file_name: config.paths.len() > 1 || Path::new(&config.paths[0]).is_dir(),
+        line_number: config.line_number,
+        byte_offset: config.byte_offset,
+        before_context: config.before_context,
+        after_context: config.after_context,
+        invert_match: config.invert_match,
+        header: config.format.has_header(),
+        multiline: config.multiline,
+        max_count: config.max_count,
+        mode: config.mode,
+        json: config.json,
+        color: config.color.enabled(),
+        stats: config.stats,
+        distance: config.fuzzy.is_some(),
+    });
+
+    let threads = match config.threads {
+        Some(threads) => threads,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+
+    let input = Arc::new(Input {
+        search_zip: config.search_zip,
+        format: config.format,
+        field: config.field,
+    });
+
+    let mut out = io::stdout().lock();
+    let summaries = if threads == 1 || files.len() == 1 {
+        let mut summaries = Vec::new();
+        let mut printed = false;
+        for path in files {
+            // The groups of context lines from different files are separated too.
+            match search_file(&mut out, &path, &printer, matcher.as_ref(), &input, printed) {
+                Ok(Some(summary)) => {
+                    printed |= summary.printed;
+                    summaries.push(summary);
+                }
+                Ok(None) => {}
+                Err(e) => skip(e, &mut failed)?,
+            }
+        }
+        summaries
+    } else {
+        search_parallel(
+            &mut out,
+            files,
+            &printer,
+            Arc::from(matcher),
+            &input,
+            threads,
+            &mut failed,
+        )?
+    };
+    printer.print_summary(&mut out, &summaries, start.elapsed())?;
+
+    // Like grep, -L succeeds when it lists a file.
+    let matched = match config.mode {
+        OutputMode::FilesWithoutMatch => summaries.iter().any(|file| file.matched_lines == 0),
+        _ => summaries.iter().any(|file| file.matched_lines > 0),
+    };
+    Ok(Outcome { matched, failed })
+}
+
+fn report(error: &MinigrepError) {
+    eprintln!("minigrep: {error}");
+}
+
+// Reports a file that couldn't be read and counts it, so the search can go
+// on. Any other error stops the search.
+fn skip(error: MinigrepError, failed: &mut usize) -> Result<(), MinigrepError> {
+    match error {
+        MinigrepError::File { .. } => {
+            report(&error);
+            *failed += 1;
+            Ok(())
+        }
+        error => Err(error),
+    }
+}
+
+// Searches the files on a pool of threads. Each file is printed to its own
+// buffer, and the buffers are written out in the same order the files would
+// have been searched in one at a time.
+fn search_parallel(
+    out: &mut impl Write,
+    files: Vec<PathBuf>,
+    printer: &Arc<Printer>,
+    matcher: Arc<dyn Matcher>,
+    input: &Arc<Input>,
+    threads: usize,
+    failed: &mut usize,
+) -> Result<Vec<FileSummary>, MinigrepError> {
+    let pool = ThreadPool::new(threads);
+    let (sender, receiver) = mpsc::channel();
+    // Set when the search stops early, so the files that haven't been started
+    // yet are skipped rather than searched for nothing.
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    let start = |index: usize, path: PathBuf, sender: &mpsc::Sender<_>| {
+        let printer = Arc::clone(printer);
+        let matcher = Arc::clone(&matcher);
+        let input = Arc::clone(input);
+        let cancelled = Arc::clone(&cancelled);
+        let sender = sender.clone();
+        pool.execute(move || {
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            let mut buffer = Vec::new();
+            // A panic would otherwise leave this file without a result, and
+            // the search would end as if it had nothing to print. It is
+            // turned into an error that stops the search instead.
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                search_file(
+                    &mut buffer,
+                    &path,
+                    &printer,
+                    matcher.as_ref(),
+                    &input,
+                    false,
+                )
+            }))
+            .unwrap_or_else(|_| {
+                Err(MinigrepError::Io(io::Error::other(format!(
+                    "the search of {} panicked",
+                    printer::display_name(&path)
+                ))))
+            });
+            // The receiver is only gone if the search stopped early, in
+            // which case nobody cares about this result.
+            let _ = sender.send((index, result.map(|summary| (summary, buffer))));
+        });
+    };
+
+    // A file's output waits in memory until the files before it have been
+    // printed, so the workers are only let this many files ahead of the next
+    // one to print. Another file is started each time one is printed.
+    let window = threads * 2;
+    let mut files = files.into_iter().enumerate();
+    for (index, path) in files.by_ref().take(window) {
+        start(index, path, &sender);
+    }
+    // The channel closes once the last file has been started and every
+    // worker is done with it, which ends the loop below.
+    let mut sender = (files.len() > 0).then_some(sender);
+
+    let mut pending = HashMap::new();
+    let mut next = 0;
+    let mut printed = false;
+    let mut summaries = Vec::new();
+    // Results that arrive before the files ahead of them wait in `pending`.
+    let mut print = |result: Result<(Option<FileSummary>, Vec<u8>), MinigrepError>| {
+        match result {
+            Ok((Some(summary), buffer)) => {
+                if summary.printed {
+                    if printed && printer.has_context() {
+                        printer.print_separator(out)?;
+                    }
+                    out.write_all(&buffer)?;
+                    printed = true;
+                }
+                summaries.push(summary);
+            }
+            Ok((None, _)) => {}
+            Err(e) => skip(e, failed)?,
+        }
+        Ok::<_, MinigrepError>(())
+    };
+    for (index, result) in &receiver {
+        pending.insert(index, result);
+        while let Some(result) = pending.remove(&next) {
+            if let Err(e) = print(result) {
+                // The workers are joined when the pool is dropped on the way
+                // out, so the files still queued mustn't be searched first.
+                cancelled.store(true, Ordering::Relaxed);
+                return Err(e);
+            }
+            next += 1;
+            if let Some(running) = &sender {
+                if let Some((index, path)) = files.next() {
+                    start(index, path, running);
+                }
+                if files.len() == 0 {
+                    sender = None;
+                }
+            }
+        }
+    }
+    Ok(summaries)
+}
+
+// How the files are read, which is the same for every file.
+#[derive(Default)]
+struct Input {
+    search_zip: bool,
+    format: InputFormat,
+    field: Option<String>,
+}
+
+// Searches one file, decompressing it if it is compressed. Returns None if the
+// file was skipped because it looks binary.
+fn search_file(
+    out: &mut impl Write,
+    path: &Path,
+    printer: &Printer,
+    matcher: &dyn Matcher,
+    input: &Input,
+    separate: bool,
+) -> Result<Option<FileSummary>, MinigrepError> {
+    let result = (|| {
+        // A path of - reads from standard input instead of a file.
+        let reader = decompress::open(path, input.search_zip).map_err(error::read_error)?;
+        let mut reader = error::FileReader(reader);
+        // Only the first block of the input is checked, so a file isn't read
+        // twice.
+        if walk::is_binary(reader.fill_buf()?) {
+            return Ok(None);
+        }
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("no file path"),
+        let Some(name) = &input.field else {
+            return printer
+                .print_file(out, path, &mut reader, matcher, separate)
+                .map(Some);
         };
+        // The columns are looked up in the header of each file, which is then
+        // put back in front of the rest so it is still counted as line 1.
+        let mut header = Vec::new();
+        if input.format.has_header() {
+            reader.read_until(b'\n', &mut header)?;
+        }
+        let header_text = String::from_utf8_lossy(&header).into_owned();
+        // A column that isn't in the header is reported like a file that
+        // can't be read.
+        let field = FieldMatcher::new(matcher, input.format, name, header_text.trim_end())
+            .map_err(error::read_error)?;
+        printer
+            .print_file(
+                out,
+                path,
+                &mut io::Cursor::new(header).chain(reader),
+                &field,
+                separate,
+            )
+            .map(Some)
+    })();
+    result.map_err(|e| error::search_error(e, path))
+}
+
+// Here the lifetimes indicate that the iterator yields matches holding string
+// slices that reference the contents argument rather than the matcher i.e. the
+// data returned by search will live as long as the data in the argument
+// contents (not the query!)
+//
+// The lines are only searched as the iterator is advanced, so a caller that
+// just wants the first match doesn't pay for searching the rest.
+pub fn search<'a>(matcher: &'a dyn Matcher, contents: &'a str) -> Matches<'a> {
+    Matches {
+        matcher,
+        lines: lines(contents),
+    }
+}
+
+/// A line that matched, along with where in the line the matches are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    /// The line number, starting at 1.
+    pub line_number: usize,
+    /// The number of the last line the match is on. It is only past
+    /// `line_number` for matches found by [`search_multiline`].
+    pub end_line_number: usize,
+    /// The byte offset of the start of the line in the contents.
+    pub offset: usize,
+    /// The line without its line ending. For a match that spans lines it is
+    /// all of them, joined by their line endings.
+    pub line: &'a str,
+    /// The byte ranges of every match within the line, from left to right.
+    pub spans: Vec<Range<usize>>,
+}
 
-        // Check if the environment variable is set.
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
+/// The iterator returned by [`search`].
+pub struct Matches<'a> {
+    matcher: &'a dyn Matcher,
+    lines: Lines<'a>,
+}
+
+impl<'a> Iterator for Matches<'a> {
+    type Item = Match<'a>;
 
-        Ok(Config {
-            query,
-            file_path,
-            ignore_case,
+    fn next(&mut self) -> Option<Match<'a>> {
+        self.lines.find_map(|line| {
+            let spans: Vec<_> = find_iter(self.matcher, line.text).collect();
+            if spans.is_empty() {
+                return None;
+            }
+            Some(Match {
+                line_number: line.number,
+                end_line_number: line.number,
+                offset: line.offset,
+                line: line.text,
+                spans,
+            })
         })
     }
 }
 
-// The unit type () is similar to void. The Box<dyn Error> means that it can
-// return any type that implements Error.
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.file_path)?;
+/// Searches the contents as a whole rather than one line at a time, so a
+/// match can span lines. Each item holds every line that a match is on, and
+/// matches that share a line are reported together.
+pub fn search_multiline<'a>(matcher: &'a dyn Matcher, contents: &'a str) -> MultilineMatches<'a> {
+    MultilineMatches {
+        contents,
+        matches: find_iter(matcher, contents),
+        pending: None,
+        counted: 0,
+        newlines: 0,
+    }
+}
+
+/// The iterator returned by [`search_multiline`].
+pub struct MultilineMatches<'a> {
+    contents: &'a str,
+    matches: FindIter<'a>,
+    // A match that was looked at to see if it shares a line with the ones
+    // before it, but didn't.
+    pending: Option<Range<usize>>,
+    // The number of newlines before the byte offset `counted`, so the line
+    // numbers are found without counting from the start each time.
+    counted: usize,
+    newlines: usize,
+}
+
+impl<'a> Iterator for MultilineMatches<'a> {
+    type Item = Match<'a>;
+
+    fn next(&mut self) -> Option<Match<'a>> {
+        let first = self.pending.take().or_else(|| self.matches.next())?;
+        // An empty match after the last line ending, like $ at the end of
+        // the contents, isn't on any line.
+        let len = self.contents.len();
+        if first.start == len && (len == 0 || self.contents.ends_with('\n')) {
+            return None;
+        }
+
+        let start = self.contents[..first.start]
+            .rfind('\n')
+            .map_or(0, |i| i + 1);
+        let mut end = line_end(self.contents, &first);
+        let mut spans = vec![first];
+        for span in self.matches.by_ref() {
+            if span.start > end {
+                self.pending = Some(span);
+                break;
+            }
+            end = end.max(line_end(self.contents, &span));
+            spans.push(span);
+        }
+
+        self.newlines += self.contents[self.counted..start].matches('\n').count();
+        self.counted = start;
+        let raw = &self.contents[start..end];
+        let line = raw.strip_suffix('\r').unwrap_or(raw);
+        let line_number = self.newlines + 1;
+        Some(Match {
+            line_number,
+            end_line_number: line_number + line.matches('\n').count(),
+            offset: start,
+            line,
+            // A match can end with the line ending, which isn't part of the
+            // line.
+            spans: spans
+                .into_iter()
+                .map(|span| {
+                    (span.start - start).min(line.len())..(span.end - start).min(line.len())
+                })
+                .collect(),
+        })
+    }
+}
 
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &contents)
+// Returns the offset of the newline ending the line the match ends on, or the
+// end of the contents.
+fn line_end(contents: &str, span: &Range<usize>) -> usize {
+    let last = if span.is_empty() {
+        span.start
     } else {
-        search_case_sensitive(&config.query, &contents)
+        span.end - 1
     };
+    contents[last..]
+        .find('\n')
+        .map_or(contents.len(), |i| last + i)
+}
 
-    for line in results {
-        println!("{line}");
-    }
+/// A line of the contents along with where it was found.
+pub struct Line<'a> {
+    /// The line number, starting at 1.
+    pub number: usize,
+    /// The byte offset of the start of the line in the contents.
+    pub offset: usize,
+    /// The line without its line ending.
+    pub text: &'a str,
+}
 
-    // This is the idiomatic way of calling run only for its side effects.
-    Ok(())
+/// Iterates over the lines of the contents the same way `str::lines` does,
+/// but also keeps track of the line numbers and byte offsets.
+pub fn lines(contents: &str) -> Lines<'_> {
+    Lines {
+        rest: contents,
+        number: 0,
+        offset: 0,
+    }
 }
 
-// Here the lifetimes indicate that the vector should return string slices that
-// reference the contents argument rather than the query argument i.e. the data
-// returned by search will live as long as the data in the argument contents
-// (not query!)
-fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    contents
-        .lines()
-        .filter(|line| line.contains(query))
-        .collect()
+/// The iterator returned by [`lines`].
+pub struct Lines<'a> {
+    rest: &'a str,
+    number: usize,
+    offset: usize,
 }
 
-fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
-    let query = query.to_lowercase();
-    contents
-        .lines()
-        .filter(|line| line.to_lowercase().contains(&query))
-        .collect()
+impl<'a> Iterator for Lines<'a> {
+    type Item = Line<'a>;
+
+    fn next(&mut self) -> Option<Line<'a>> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let len = self.rest.find('\n').map_or(self.rest.len(), |i| i + 1);
+        let (raw, rest) = self.rest.split_at(len);
+        self.rest = rest;
+
+        let text = raw.strip_suffix('\n').unwrap_or(raw);
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        self.number += 1;
+        let line = Line {
+            number: self.number,
+            offset: self.offset,
+            text,
+        };
+        self.offset += raw.len();
+        Some(line)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::TempDir;
+    use std::{env, fs, process, sync::atomic::AtomicUsize};
 
     #[test]
     fn case_sensitive() {
@@ -93,7 +700,9 @@ Duct tape.";
 
         assert_eq!(
             vec!["safe, fast, productive."],
-            search_case_sensitive(query, contents)
+            search(&Substring::new(query, false), contents)
+                .map(|m| m.line)
+                .collect::<Vec<_>>()
         );
     }
 
@@ -109,7 +718,409 @@ Trust me.";
 
         assert_eq!(
             vec!["Rust:", "Trust me."],
-            search_case_insensitive(query, contents)
+            search(&Substring::new(query, true), contents)
+                .map(|m| m.line)
+                .collect::<Vec<_>>()
         );
     }
+
+    #[test]
+    fn match_spans() {
+        let matcher = Substring::new("us", false);
+        let contents = "Rust uses:\r\nsafe, fast, productive.\nTrust us.";
+        let mut matches = search(&matcher, contents);
+
+        assert_eq!(
+            Some(Match {
+                line_number: 1,
+                end_line_number: 1,
+                offset: 0,
+                line: "Rust uses:",
+                spans: vec![1..3, 5..7],
+            }),
+            matches.next()
+        );
+        assert_eq!(
+            Some(Match {
+                line_number: 3,
+                end_line_number: 3,
+                offset: 36,
+                line: "Trust us.",
+                spans: vec![2..4, 6..8],
+            }),
+            matches.next()
+        );
+        assert_eq!(None, matches.next());
+    }
+
+    #[test]
+    fn line_offsets() {
+        let offsets: Vec<_> = lines("a\r\nbc\n\nd")
+            .map(|line| (line.number, line.offset, line.text))
+            .collect();
+
+        assert_eq!(
+            vec![(1, 0, "a"), (2, 3, "bc"), (3, 6, ""), (4, 7, "d")],
+            offsets
+        );
+    }
+
+    #[test]
+    fn regex() {
+        let matcher = Regex::new(r"^\w+:$|three", false).unwrap();
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        assert_eq!(
+            vec!["Rust:", "Pick three."],
+            search(&matcher, contents)
+                .map(|m| m.line)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let config = |regex| Config {
+            patterns: vec!["fast".to_string(), "^T".to_string(), "ick".to_string()],
+            regex,
+            ..Config::default()
+        };
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let matcher = config(false).matcher().unwrap();
+        assert_eq!(
+            vec!["safe, fast, productive.", "Pick three."],
+            search(matcher.as_ref(), contents)
+                .map(|m| m.line)
+                .collect::<Vec<_>>()
+        );
+        let matcher = config(true).matcher().unwrap();
+        assert_eq!(
+            vec!["safe, fast, productive.", "Pick three.", "Trust me."],
+            search(matcher.as_ref(), contents)
+                .map(|m| m.line)
+                .collect::<Vec<_>>()
+        );
+
+        let config = Config {
+            patterns: vec!["a)|(?:b".to_string(), "c".to_string()],
+            regex: true,
+            ..Config::default()
+        };
+        assert!(config.matcher().is_err());
+
+        // No patterns, as from an empty pattern file, match nothing.
+        for regex in [false, true] {
+            let config = Config {
+                regex,
+                ..Config::default()
+            };
+            let matcher = config.matcher().unwrap();
+            assert_eq!(0, search(matcher.as_ref(), contents).count());
+        }
+    }
+
+    #[test]
+    fn smart_case() {
+        let config = |query: &str| Config {
+            patterns: vec![query.to_string()],
+            smart_case: true,
+            ..Config::default()
+        };
+
+        assert!(config("rust").matcher().unwrap().is_match("Trust"));
+        assert!(!config("Rust").matcher().unwrap().is_match("trust"));
+        assert!(config("Rust").matcher().unwrap().is_match("Rust"));
+    }
+
+    #[test]
+    fn parallel_output_is_in_order() {
+        let dir = TempDir::new("parallel");
+        let files: Vec<PathBuf> = (0..20)
+            .map(|i| {
+                let path = dir.join(format!("{i:02}.txt"));
+                fs::write(&path, format!("line {i}\nnobody {i}\n").repeat(i)).unwrap();
+                path
+            })
+            .collect();
+        let printer = Arc::new(Printer {
+            with_file_name: true,
+            line_number: true,
+            before_context: 1,
+            ..Printer::default()
+        });
+
+        let mut sequential = Vec::new();
+        let mut printed = false;
+        for path in &files {
+            let summary = search_file(
+                &mut sequential,
+                path,
+                &printer,
+                &Substring::new("nobody", false),
+                &Input::default(),
+                printed,
+            )
+            .unwrap();
+            printed |= summary.unwrap().printed;
+        }
+        let mut parallel = Vec::new();
+        search_parallel(
+            &mut parallel,
+            files,
+            &printer,
+            Arc::new(Substring::new("nobody", false)),
+            &Arc::new(Input::default()),
+            4,
+            &mut 0,
+        )
+        .unwrap();
+
+        assert_eq!(
+            String::from_utf8(sequential).unwrap(),
+            String::from_utf8(parallel).unwrap()
+        );
+    }
+
+    #[test]
+    fn parallel_search_stops_on_output_error() {
+        // Counts the lines it is asked to search.
+        struct Counting(AtomicUsize);
+        impl Matcher for Counting {
+            fn find_at(&self, _: &str, start: usize) -> Option<Range<usize>> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                Some(start..start)
+            }
+        }
+        struct Closed;
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let dir = TempDir::new("cancel");
+        let files: Vec<PathBuf> = (0..100)
+            .map(|i| {
+                let path = dir.join(format!("{i:03}.txt"));
+                fs::write(&path, "line\n").unwrap();
+                path
+            })
+            .collect();
+        let matcher = Arc::new(Counting(AtomicUsize::new(0)));
+        let result = search_parallel(
+            &mut Closed,
+            files,
+            &Arc::new(Printer::default()),
+            Arc::clone(&matcher) as Arc<dyn Matcher>,
+            &Arc::new(Input::default()),
+            4,
+            &mut 0,
+        );
+
+        assert!(result.is_err());
+        // Only the files the workers were let ahead to were searched.
+        assert!(matcher.0.load(Ordering::Relaxed) <= 4 * 2 * 2);
+    }
+
+    #[test]
+    fn parallel_search_fails_when_a_worker_panics() {
+        struct Panicking;
+        impl Matcher for Panicking {
+            fn find_at(&self, haystack: &str, _: usize) -> Option<Range<usize>> {
+                if haystack == "bad" {
+                    panic!("can't search this");
+                }
+                None
+            }
+        }
+
+        let dir = TempDir::new("panic");
+        let files: Vec<PathBuf> = ["good", "bad", "good"]
+            .iter()
+            .enumerate()
+            .map(|(i, contents)| {
+                let path = dir.join(format!("{i}.txt"));
+                fs::write(&path, contents).unwrap();
+                path
+            })
+            .collect();
+        let mut failed = 0;
+        let result = search_parallel(
+            &mut Vec::new(),
+            files,
+            &Arc::new(Printer::default()),
+            Arc::new(Panicking),
+            &Arc::new(Input::default()),
+            2,
+            &mut failed,
+        );
+
+        match result {
+            Err(e @ MinigrepError::Io(_)) => assert!(e.to_string().ends_with("1.txt panicked")),
+            _ => panic!("expected an error for the panic"),
+        }
+        assert_eq!(0, failed);
+    }
+
+    #[test]
+    fn csv_field() {
+        let dir = TempDir::new("field");
+        let path = dir.join("poets.csv");
+        fs::write(
+            &path,
+            "name,poem\nDickinson,\"I'm nobody, who are you?\"\nnobody,Ozymandias\n",
+        )
+        .unwrap();
+        let printer = Printer {
+            line_number: true,
+            header: true,
+            ..Printer::default()
+        };
+        let input = Input {
+            format: InputFormat::Csv,
+            field: Some("poem".to_string()),
+            ..Input::default()
+        };
+
+        let mut out = Vec::new();
+        search_file(
+            &mut out,
+            &path,
+            &printer,
+            &Substring::new("nobody", false),
+            &input,
+            false,
+        )
+        .unwrap();
+        // The header is never matched, even when it names the column.
+        let mut names = Vec::new();
+        search_file(
+            &mut names,
+            &path,
+            &printer,
+            &Substring::new("name", false),
+            &Input {
+                field: Some("1".to_string()),
+                ..input
+            },
+            false,
+        )
+        .unwrap();
+        // A misspelled column is an error rather than a file without matches.
+        let missing = search_file(
+            &mut Vec::new(),
+            &path,
+            &printer,
+            &Substring::new("nobody", false),
+            &Input {
+                format: InputFormat::Csv,
+                field: Some("nope".to_string()),
+                ..Input::default()
+            },
+            false,
+        );
+
+        match missing {
+            Err(e @ MinigrepError::File { .. }) => {
+                assert!(e.to_string().ends_with("no column `nope`"))
+            }
+            _ => panic!("expected an error for the missing column"),
+        }
+        assert_eq!(
+            "2:Dickinson,\"I'm nobody, who are you?\"\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!("", String::from_utf8(names).unwrap());
+    }
+
+    #[test]
+    fn multiline() {
+        let matcher = Substring::new("t,\nproductive.\r\nPick", false);
+        let contents = "Rust:\nsafe, fast,\nproductive.\r\nPick three.\nTrust me.\n";
+        let mut matches = search_multiline(&matcher, contents);
+
+        let found = matches.next().unwrap();
+        assert_eq!(
+            (2, 4, 6, "safe, fast,\nproductive.\r\nPick three."),
+            (
+                found.line_number,
+                found.end_line_number,
+                found.offset,
+                found.line
+            )
+        );
+        assert_eq!(9..29, found.spans[0]);
+        assert_eq!(None, matches.next());
+    }
+
+    #[test]
+    fn multiline_blocks() {
+        type Found<'a> = Vec<(usize, usize, &'a str, Vec<Range<usize>>)>;
+        fn found<'a>(matcher: &'a dyn Matcher, contents: &'a str) -> Found<'a> {
+            search_multiline(matcher, contents)
+                .map(|m| (m.line_number, m.end_line_number, m.line, m.spans))
+                .collect()
+        }
+
+        // Matches that share a line are reported together.
+        let config = Config {
+            patterns: vec![r"t\ns".to_string(), "ow".to_string()],
+            multiline: true,
+            ..Config::default()
+        };
+        let matcher = config.matcher().unwrap();
+        assert_eq!(
+            vec![(1, 2, "fast\nslow", vec![3..6, 7..9])],
+            found(matcher.as_ref(), "fast\nslow\nfast")
+        );
+
+        // A match ending with a line ending doesn't take the next line with
+        // it, and there is no line after the last line ending.
+        let regex = Regex::new(r"^|e\.\n|o", false).unwrap();
+        assert_eq!(
+            vec![
+                (1, 1, "one.", vec![0..0, 2..4]),
+                (2, 2, "too", vec![1..2, 2..3])
+            ],
+            found(&regex, "one.\ntoo\n")
+        );
+    }
+
+    #[test]
+    fn unreadable_files_are_skipped() {
+        let printer = Printer::default();
+        let matcher = Substring::new("nobody", false);
+        let missing = env::temp_dir().join(format!("minigrep-missing-{}", process::id()));
+        let result = search_file(
+            &mut Vec::new(),
+            &missing,
+            &printer,
+            &matcher,
+            &Input::default(),
+            false,
+        );
+        assert!(matches!(result, Err(MinigrepError::File { path, .. }) if path == missing));
+
+        let mut failed = 0;
+        let error = MinigrepError::File {
+            path: missing,
+            source: io::ErrorKind::NotFound.into(),
+        };
+        assert!(skip(error, &mut failed).is_ok());
+        let error = MinigrepError::Io(io::ErrorKind::BrokenPipe.into());
+        assert!(skip(error, &mut failed).is_err());
+        assert_eq!(1, failed);
+    }
 }
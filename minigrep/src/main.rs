@@ -1,25 +1,59 @@
-use minigrep::{run, Config};
+use minigrep::{build_index, interactive, run, Command, MinigrepError, Outcome, USAGE, VERSION};
 use std::{env, process};
 
 // The main function should only handle argument parsing, config setup, calling
 // run, and handling errors from run.
 fn main() {
-    // Not longer collect args and just pass the iterator into Config::new.
+    // Not longer collect args and just pass the iterator into Command::parse.
     // let args: Vec<String> = env::args().collect();
-    let config = Config::new(env::args()).unwrap_or_else(|err| {
+    let command = Command::parse(env::args()).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        eprintln!("Try `minigrep --help` for more information.");
+        process::exit(MinigrepError::Args(err).exit_code());
     });
 
-    println!(
-        "Searching for {} in file {}.",
-        config.query, config.file_path
-    );
+    let config = match command {
+        Command::Search(config) => config,
+        Command::Index { dirs, walker } => {
+            if let Err(e) = build_index(&dirs, &walker) {
+                report(&e);
+                process::exit(e.exit_code());
+            }
+            return;
+        }
+        Command::Help => {
+            print!("{USAGE}");
+            return;
+        }
+        Command::Version => {
+            println!("minigrep {VERSION}");
+            return;
+        }
+    };
 
-    // We don't use unwrap_or_else here because we don't care about the Ok
-    // return value of run, only the error.
-    if let Err(e) = run(config) {
+    // Like grep, the exit status is 0 if a line matched, 1 if none did and 2
+    // if something went wrong.
+    let result = if config.interactive {
+        interactive(config).map(|()| Outcome {
+            matched: true,
+            failed: 0,
+        })
+    } else {
+        run(config)
+    };
+    match result {
+        Ok(outcome) => process::exit(outcome.exit_code()),
+        Err(e) => {
+            report(&e);
+            process::exit(e.exit_code());
+        }
+    }
+}
+
+// Prints the error, unless it is only the output having been closed, like
+// when piping into `head`.
+fn report(e: &MinigrepError) {
+    if !e.is_broken_pipe() {
         eprintln!("Application error: {e}");
-        process::exit(1);
     }
 }
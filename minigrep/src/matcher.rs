@@ -0,0 +1,225 @@
+use std::ops::Range;
+
+// A Matcher knows how to find a query inside a haystack. Plain substring search
+// and the regular expression engine both implement it, so the search functions
+// don't need to care which one the user asked for. Matchers are shared between
+// the threads searching files, so they have to be Send and Sync.
+pub trait Matcher: Send + Sync {
+    /// Returns the byte range of the leftmost match in `haystack` that starts
+    /// at or after `start`, or `None` if there is no such match.
+    ///
+    /// `start` must lie on a char boundary.
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>>;
+
+    /// Returns the bounds of every capture group for the leftmost match
+    /// starting at or after `start`, where group 0 is the whole match. Groups
+    /// that did not take part in the match are `None`. Only regexes have
+    /// groups other than group 0.
+    fn captures_at(&self, haystack: &str, start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        self.find_at(haystack, start).map(|found| vec![Some(found)])
+    }
+
+    /// Returns the edit distance between the query and a match found by this
+    /// matcher. Only approximate matchers have one, exact matches are `None`.
+    fn distance(&self, _haystack: &str, _found: Range<usize>) -> Option<usize> {
+        None
+    }
+
+    /// Returns true if the matcher matches anywhere in `haystack`.
+    fn is_match(&self, haystack: &str) -> bool {
+        self.find_at(haystack, 0).is_some()
+    }
+}
+
+/// Returns an iterator over the non-overlapping matches in `haystack`, from
+/// left to right.
+pub fn find_iter<'a>(matcher: &'a dyn Matcher, haystack: &'a str) -> FindIter<'a> {
+    FindIter {
+        matcher,
+        haystack,
+        pos: 0,
+        last_end: None,
+    }
+}
+
+pub struct FindIter<'a> {
+    matcher: &'a dyn Matcher,
+    haystack: &'a str,
+    pos: usize,
+    last_end: Option<usize>,
+}
+
+impl Iterator for FindIter<'_> {
+    type Item = Range<usize>;
+
+    fn next(&mut self) -> Option<Range<usize>> {
+        loop {
+            if self.pos > self.haystack.len() {
+                return None;
+            }
+            let found = self.matcher.find_at(self.haystack, self.pos)?;
+            if found.is_empty() {
+                // An empty match would be found again at the same spot, so
+                // step over the next char. An empty match right where the
+                // previous match ended isn't reported.
+                let next = self.haystack[found.end..].chars().next();
+                self.pos = found.end + next.map_or(1, char::len_utf8);
+                if self.last_end == Some(found.end) {
+                    continue;
+                }
+            } else {
+                self.pos = found.end;
+            }
+            self.last_end = Some(found.end);
+            return Some(found);
+        }
+    }
+}
+
+/// Returns the case folded form of a char, which is what two chars are compared
+/// by when case is ignored.
+///
+/// Lowercasing alone isn't enough: 'ß' and "SS" only compare equal once both
+/// are uppercased and then lowercased again, and the same trick makes the
+/// Turkish dotless 'ı' and the long 'ſ' equal to 'i' and 's'. Lowercasing
+/// first makes the capital 'ẞ' end up the same as 'ß'. The folded form can be
+/// longer than the char it came from, so it is an iterator.
+pub fn fold_case(c: char) -> impl Iterator<Item = char> {
+    c.to_lowercase()
+        .flat_map(char::to_uppercase)
+        .flat_map(char::to_lowercase)
+}
+
+// This is the original search behavior: the query is matched as a plain string.
+pub struct Substring {
+    query: String,
+    // The case folded query, only used when ignoring case.
+    folded: Vec<char>,
+    ignore_case: bool,
+}
+
+impl Substring {
+    pub fn new(query: &str, ignore_case: bool) -> Substring {
+        Substring {
+            query: query.to_string(),
+            folded: query.chars().flat_map(fold_case).collect(),
+            ignore_case,
+        }
+    }
+}
+
+impl Matcher for Substring {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        if !self.ignore_case {
+            return haystack[start..]
+                .find(&self.query)
+                .map(|i| start + i..start + i + self.query.len());
+        }
+
+        // Folding the whole haystack would give us offsets into the folded
+        // copy rather than the original line, and the two drift apart as soon
+        // as a char folds to a different length. So instead we try to match at
+        // every char boundary and fold the chars of the line as we go.
+        haystack[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(std::iter::once(haystack.len()))
+            .find_map(|i| match_folded(&self.folded, &haystack[i..]).map(|len| i..i + len))
+    }
+}
+
+// Returns the number of bytes of text consumed when the folded text starts
+// with the (already folded) query. A match has to end on a char boundary of
+// the text, so a query of "s" doesn't match half of a 'ß'.
+fn match_folded(query: &[char], text: &str) -> Option<usize> {
+    let mut remaining = query;
+    for (i, c) in text.char_indices() {
+        if remaining.is_empty() {
+            return Some(i);
+        }
+        for folded in fold_case(c) {
+            match remaining.split_first() {
+                Some((&q, rest)) if q == folded => remaining = rest,
+                _ => return None,
+            }
+        }
+    }
+    if remaining.is_empty() {
+        Some(text.len())
+    } else {
+        None
+    }
+}
+
+/// Returns true if the query has an uppercase letter in it, which is what
+/// smart case uses to decide whether to ignore case. The escapes in a regex
+/// like `\W` and `\B` aren't letters the user typed, so they don't count.
+pub fn has_uppercase(query: &str, regex: bool) -> bool {
+    let mut chars = query.chars();
+    while let Some(c) = chars.next() {
+        if regex && c == '\\' {
+            chars.next();
+        } else if c.is_uppercase() {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substring_offsets() {
+        let matcher = Substring::new("duct", false);
+        assert_eq!(Some(15..19), matcher.find_at("safe, fast, productive.", 0));
+        assert_eq!(None, matcher.find_at("safe, fast, productive.", 16));
+    }
+
+    #[test]
+    fn substring_ignore_case_offsets() {
+        let matcher = Substring::new("RUST", true);
+        assert_eq!(Some(4..8), matcher.find_at("¡¡Rust!", 0));
+        assert!(!matcher.is_match("Rus t"));
+    }
+
+    #[test]
+    fn unicode_case_folding() {
+        let matcher = Substring::new("STRASSE", true);
+        assert_eq!(Some(4..11), matcher.find_at("Die Straße", 0));
+
+        // The offsets are into the original line, even though 'ß' folds to
+        // two chars and 'İ' to a char and a combining dot.
+        let matcher = Substring::new("ß", true);
+        assert_eq!(Some(1..3), matcher.find_at("aSSb", 0));
+        assert_eq!(Some(4..6), matcher.find_at("İ: ß", 0));
+        assert!(!Substring::new("s", true).is_match("ß"));
+        assert!(Substring::new("kılıç", true).is_match("KILIÇ"));
+        assert!(Substring::new("ΣΟΦΟΣ", true).is_match("σοφος"));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(has_uppercase("Rust", false));
+        assert!(!has_uppercase("rust", false));
+        assert!(!has_uppercase(r"\w+\W\d", true));
+        assert!(has_uppercase(r"\w+\W\d", false));
+        assert!(has_uppercase(r"\bÉté", true));
+    }
+
+    #[test]
+    fn find_all() {
+        let matcher = Substring::new("ab", false);
+        assert_eq!(
+            vec![0..2, 3..5],
+            find_iter(&matcher, "ab ab a").collect::<Vec<_>>()
+        );
+
+        let matcher = crate::Regex::new("x*", false).unwrap();
+        assert_eq!(
+            vec![0..0, 1..3, 4..4],
+            find_iter(&matcher, "axxb").collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+// This is the same design as the ThreadPool in the web_server project. A fixed
+// number of workers share the receiving end of a channel and each one runs the
+// jobs it manages to pull off of it.
+pub struct ThreadPool {
+    workers: Vec<Worker>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+struct Worker {
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+impl ThreadPool {
+    /// Create a new ThreadPool.
+    ///
+    /// The size is the number of threads in the pool.
+    ///
+    /// # Panics
+    ///
+    /// The `new` function will panic if the size is zero.
+    pub fn new(size: usize) -> ThreadPool {
+        assert!(size > 0);
+
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|_| Worker::new(Arc::clone(&receiver)))
+            .collect();
+
+        ThreadPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender.as_ref().unwrap().send(Box::new(f)).unwrap();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // Dropping the sender closes the channel, which tells the workers to
+        // stop once they have finished the jobs that are left.
+        drop(self.sender.take());
+
+        for worker in &mut self.workers {
+            // A worker that panicked has already stopped, and panicking here
+            // too would only hide whatever error is on its way out.
+            if let Some(thread) = worker.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+impl Worker {
+    fn new(receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
+        let thread = thread::spawn(move || loop {
+            // The lock is released at the end of this statement, so other
+            // workers can receive jobs while this one runs.
+            let message = receiver.lock().unwrap().recv();
+
+            match message {
+                Ok(job) => job(),
+                Err(_) => break,
+            }
+        });
+
+        Worker {
+            thread: Some(thread),
+        }
+    }
+}
@@ -0,0 +1,941 @@
+use std::{
+    borrow::Cow,
+    collections::VecDeque,
+    env,
+    fmt::Display,
+    io::{self, BufRead, IsTerminal, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{json, lines, matcher::find_iter, search_multiline, Line, Match, Matcher};
+
+/// What gets printed for each file.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    /// The matching lines, with any context around them.
+    #[default]
+    Lines,
+    /// The number of matching lines.
+    Count,
+    /// Only the file name, if the file has a matching line.
+    FilesWithMatches,
+    /// Only the file name, if the file has no matching line.
+    FilesWithoutMatch,
+}
+
+/// When to color the output.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and NO_COLOR isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Decides whether output written to standard output should be colored.
+    pub fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // See https://no-color.org, an empty NO_COLOR doesn't count.
+            ColorChoice::Auto => {
+                io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+            }
+        }
+    }
+}
+
+// The same colors GNU grep uses by default.
+pub(crate) const FILE_NAME_COLOR: &str = "35";
+pub(crate) const LINE_NUMBER_COLOR: &str = "32";
+pub(crate) const SEPARATOR_COLOR: &str = "36";
+pub(crate) const MATCH_COLOR: &str = "1;31";
+
+/// Writes the matching lines of a file along with the position information
+/// and context lines that were asked for.
+#[derive(Default)]
+pub struct Printer {
+    pub with_file_name: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    /// Number of lines to print before each match.
+    pub before_context: usize,
+    /// Number of lines to print after each match.
+    pub after_context: usize,
+    /// Select the lines that don't match instead.
+    pub invert_match: bool,
+    /// The first line names the columns of a CSV or TSV file, so it is never
+    /// selected.
+    pub header: bool,
+    /// Search each file as a whole so matches can span lines, printing every
+    /// line a match is on.
+    pub multiline: bool,
+    /// Stop searching the file after this many matching lines.
+    pub max_count: Option<usize>,
+    pub mode: OutputMode,
+    /// Print JSON Lines records instead of text.
+    pub json: bool,
+    /// Highlight matches, file names and line numbers with ANSI escapes.
+    pub color: bool,
+    /// Count the matches in each file and print statistics at the end.
+    pub stats: bool,
+    /// Print how far off each matching line is from the query, for
+    /// approximate matches. Finding that out searches the line again, so it
+    /// is only done when the matcher can have a distance.
+    pub distance: bool,
+}
+
+/// What happened while searching one file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FileSummary {
+    pub path: PathBuf,
+    /// Whether anything was printed for the file.
+    pub printed: bool,
+    pub matched_lines: usize,
+    /// The number of matches in the matching lines. Only counted for --stats.
+    pub matches: usize,
+    pub bytes_searched: usize,
+}
+
+impl Printer {
+    /// Reads the lines from `reader` one at a time and prints the ones that
+    /// match, or their count or the file name depending on the mode.
+    ///
+    /// Only the current line and the lines kept for context are held in
+    /// memory, so the input can be larger than memory. Invalid UTF-8 is
+    /// replaced rather than treated as an error.
+    ///
+    /// When `separate` is true an earlier file already printed lines, so the
+    /// first group of lines is separated from them.
+    pub fn print_file(
+        &self,
+        out: &mut impl Write,
+        path: &Path,
+        reader: &mut impl BufRead,
+        matcher: &dyn Matcher,
+        separate: bool,
+    ) -> io::Result<FileSummary> {
+        let mut file = FileState {
+            path,
+            matcher,
+            last_printed: None,
+            separate: separate && self.has_context(),
+            matches: 0,
+        };
+        if self.json {
+            write!(out, "{{\"type\":\"begin\",\"path\":")?;
+            json::write_string(out, &display_name(path))?;
+            writeln!(out, "}}")?;
+        }
+        if self.multiline {
+            return self.print_multiline(out, &mut file, reader);
+        }
+        // Lines that might be needed as context before the next match. Only
+        // the last `before_context` of them are kept.
+        let mut before: VecDeque<BufferedLine> = VecDeque::with_capacity(self.before_context);
+        let mut after_remaining = 0;
+        let mut count = 0;
+
+        let mut buf = Vec::new();
+        let mut number = 0;
+        let mut offset = 0;
+        loop {
+            buf.clear();
+            let len = reader.read_until(b'\n', &mut buf)?;
+            if len == 0 {
+                break;
+            }
+            number += 1;
+            let text = String::from_utf8_lossy(trim_line_ending(&buf));
+            let line = Line {
+                number,
+                offset,
+                text: &text,
+            };
+            offset += len;
+
+            // Once max_count lines have matched, the rest of the lines are
+            // only looked at to finish printing the context after the last one.
+            let done = self.max_count.is_some_and(|max| count >= max);
+            let header = self.header && number == 1;
+            if !done && !header && matcher.is_match(line.text) != self.invert_match {
+                count += 1;
+                if self.stats && !self.invert_match {
+                    file.matches += find_iter(matcher, line.text).count();
+                }
+                match self.mode {
+                    OutputMode::Lines => {
+                        for context in before.drain(..) {
+                            self.print_line(out, &mut file, &context.as_line(), '-')?;
+                        }
+                        self.print_line(out, &mut file, &line, ':')?;
+                        after_remaining = self.after_context;
+                    }
+                    // One match is enough to know whether the file name is
+                    // printed, unless the matches are being counted.
+                    OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch if !self.stats => {
+                        break
+                    }
+                    OutputMode::Count
+                    | OutputMode::FilesWithMatches
+                    | OutputMode::FilesWithoutMatch => {}
+                }
+            } else if after_remaining > 0 {
+                self.print_line(out, &mut file, &line, '-')?;
+                after_remaining -= 1;
+            } else if done {
+                break;
+            } else if self.before_context > 0 {
+                if before.len() == self.before_context {
+                    before.pop_front();
+                }
+                before.push_back(BufferedLine {
+                    number,
+                    offset: line.offset,
+                    text: text.into_owned(),
+                });
+            }
+        }
+
+        self.finish_file(out, &file, count, offset)
+    }
+
+    // Unlike the lines, the whole file has to be held in memory to find
+    // matches that span lines. Every line a match is on is printed as a
+    // matching line, and the context is counted from the first and last of
+    // them.
+    fn print_multiline(
+        &self,
+        out: &mut impl Write,
+        file: &mut FileState,
+        reader: &mut impl BufRead,
+    ) -> io::Result<FileSummary> {
+        let mut buf = Vec::new();
+        reader.read_to_end(&mut buf)?;
+        let contents = String::from_utf8_lossy(&buf);
+        let all: Vec<Line> = lines(&contents).collect();
+
+        let mut count = 0;
+        // The last line to print as context after the previous match.
+        let mut after_until = 0;
+        let max_count = self.max_count.unwrap_or(usize::MAX);
+        for block in search_multiline(file.matcher, &contents).take(max_count) {
+            count += block.end_line_number - block.line_number + 1;
+            file.matches += block.spans.len();
+            match self.mode {
+                OutputMode::Lines => {}
+                OutputMode::FilesWithMatches | OutputMode::FilesWithoutMatch if !self.stats => {
+                    break
+                }
+                OutputMode::Count
+                | OutputMode::FilesWithMatches
+                | OutputMode::FilesWithoutMatch => continue,
+            }
+
+            let first = block.line_number - 1;
+            let printed = file.last_printed.unwrap_or(0);
+            for line in &all[printed..after_until.min(first)] {
+                self.print_line(out, file, line, '-')?;
+            }
+            let printed = file.last_printed.unwrap_or(0);
+            for line in &all[printed.max(first.saturating_sub(self.before_context))..first] {
+                self.print_line(out, file, line, '-')?;
+            }
+            self.print_block(out, file, &block)?;
+            after_until = block.end_line_number + self.after_context;
+        }
+        let printed = file.last_printed.unwrap_or(0);
+        if printed > 0 {
+            for line in &all[printed..after_until.min(all.len())] {
+                self.print_line(out, file, line, '-')?;
+            }
+        }
+
+        self.finish_file(out, file, count, buf.len())
+    }
+
+    // Prints the lines of a multiline match, highlighting the parts of each
+    // line the matches cover.
+    fn print_block(
+        &self,
+        out: &mut impl Write,
+        file: &mut FileState,
+        block: &Match,
+    ) -> io::Result<()> {
+        if self.json {
+            let line = Line {
+                number: block.line_number,
+                offset: block.offset,
+                text: block.line,
+            };
+            return self.print_json_record(
+                out,
+                file,
+                &line,
+                block.end_line_number,
+                Some(&block.spans),
+            );
+        }
+
+        let mut start = 0;
+        for (i, raw) in block.line.split('\n').enumerate() {
+            let text = raw.strip_suffix('\r').unwrap_or(raw);
+            let line = Line {
+                number: block.line_number + i,
+                offset: block.offset + start,
+                text,
+            };
+            self.print_prefix(out, file, &line, ':')?;
+            let end = start + text.len();
+            let spans = block
+                .spans
+                .iter()
+                .filter(|span| span.start <= end && span.end >= start)
+                .map(|span| span.start.max(start) - start..span.end.min(end) - start);
+            self.print_text(out, text, spans)?;
+            start += raw.len() + 1;
+        }
+        Ok(())
+    }
+
+    // Prints the counts or file name that go after the lines, or the record
+    // that ends the file for --json.
+    fn finish_file(
+        &self,
+        out: &mut impl Write,
+        file: &FileState,
+        count: usize,
+        offset: usize,
+    ) -> io::Result<FileSummary> {
+        let path = file.path;
+        let mut summary = FileSummary {
+            path: path.to_path_buf(),
+            printed: file.last_printed.is_some(),
+            matched_lines: count,
+            matches: file.matches,
+            bytes_searched: offset,
+        };
+        if self.json {
+            // The end record carries the count, so the count and file name
+            // modes don't print anything else.
+            write!(out, "{{\"type\":\"end\",\"path\":")?;
+            json::write_string(out, &display_name(path))?;
+            write!(out, ",\"matched_lines\":{count}")?;
+            if self.stats {
+                write!(out, ",\"matches\":{}", file.matches)?;
+            }
+            writeln!(out, ",\"bytes_searched\":{offset}}}")?;
+            summary.printed = true;
+            return Ok(summary);
+        }
+
+        match self.mode {
+            OutputMode::Lines => {}
+            OutputMode::Count => {
+                // Like grep, the count is printed even when it is zero.
+                if self.with_file_name {
+                    self.paint(out, FILE_NAME_COLOR, display_name(path))?;
+                    self.paint(out, SEPARATOR_COLOR, ':')?;
+                }
+                writeln!(out, "{count}")?;
+                summary.printed = true;
+            }
+            OutputMode::FilesWithMatches => {
+                if count > 0 {
+                    self.paint(out, FILE_NAME_COLOR, display_name(path))?;
+                    writeln!(out)?;
+                }
+                summary.printed = count > 0;
+            }
+            OutputMode::FilesWithoutMatch => {
+                if count == 0 {
+                    self.paint(out, FILE_NAME_COLOR, display_name(path))?;
+                    writeln!(out)?;
+                }
+                summary.printed = count == 0;
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Prints the record that ends the --json output, with the totals over
+    /// every file. With --stats the totals are printed as text otherwise,
+    /// after the matches in each file.
+    pub fn print_summary(
+        &self,
+        out: &mut impl Write,
+        files: &[FileSummary],
+        elapsed: Duration,
+    ) -> io::Result<()> {
+        let matched: Vec<_> = files.iter().filter(|file| file.matched_lines > 0).collect();
+        let matched_lines: usize = matched.iter().map(|file| file.matched_lines).sum();
+        let matches: usize = matched.iter().map(|file| file.matches).sum();
+        let bytes_searched: usize = files.iter().map(|file| file.bytes_searched).sum();
+
+        if self.json {
+            write!(
+                out,
+                "{{\"type\":\"summary\",\"files_searched\":{},\"files_matched\":{},\
+                 \"matched_lines\":{matched_lines},\"bytes_searched\":{bytes_searched}",
+                files.len(),
+                matched.len(),
+            )?;
+            if self.stats {
+                write!(
+                    out,
+                    ",\"matches\":{matches},\"elapsed_secs\":{:.6}",
+                    elapsed.as_secs_f64()
+                )?;
+            }
+            return writeln!(out, "}}");
+        }
+        if !self.stats {
+            return Ok(());
+        }
+
+        // The statistics are set apart from whatever was printed before.
+        if files.iter().any(|file| file.printed) {
+            writeln!(out)?;
+        }
+        for file in &matched {
+            self.paint(out, FILE_NAME_COLOR, display_name(&file.path))?;
+            self.paint(out, SEPARATOR_COLOR, ':')?;
+            writeln!(
+                out,
+                " {} in {}",
+                count(file.matches, "match", "matches"),
+                count(file.matched_lines, "line", "lines")
+            )?;
+        }
+        writeln!(out, "{}", count(matches, "match", "matches"))?;
+        writeln!(
+            out,
+            "{}",
+            count(matched_lines, "matched line", "matched lines")
+        )?;
+        writeln!(
+            out,
+            "{} contained matches",
+            count(matched.len(), "file", "files")
+        )?;
+        writeln!(out, "{} searched", count(files.len(), "file", "files"))?;
+        writeln!(out, "{} searched", count(bytes_searched, "byte", "bytes"))?;
+        writeln!(out, "{:.6} seconds elapsed", elapsed.as_secs_f64())
+    }
+
+    /// Returns true when context lines are printed, which is when groups of
+    /// lines are separated with `--`.
+    pub fn has_context(&self) -> bool {
+        !self.json
+            && self.mode == OutputMode::Lines
+            && (self.before_context > 0 || self.after_context > 0)
+    }
+
+    // Like grep, matching lines use a : after the file name, line number and
+    // byte offset while context lines use a -.
+    fn print_line(
+        &self,
+        out: &mut impl Write,
+        file: &mut FileState,
+        line: &Line,
+        separator: char,
+    ) -> io::Result<()> {
+        if self.json {
+            return self.print_json_line(out, file, line, separator == ':');
+        }
+
+        self.print_prefix(out, file, line, separator)?;
+        // Only the matches in matching lines are highlighted. The lines
+        // selected by --invert-match don't have any.
+        if !self.color || separator != ':' || self.invert_match {
+            return writeln!(out, "{}", line.text);
+        }
+        self.print_text(out, line.text, find_iter(file.matcher, line.text))
+    }
+
+    // Prints what goes in front of a line: the separator from the previous
+    // group of lines if there is a gap, then the file name, line number and
+    // byte offset that were asked for.
+    fn print_prefix(
+        &self,
+        out: &mut impl Write,
+        file: &mut FileState,
+        line: &Line,
+        separator: char,
+    ) -> io::Result<()> {
+        let gap = match file.last_printed {
+            Some(last) => self.has_context() && line.number > last + 1,
+            None => file.separate,
+        };
+        if gap {
+            self.print_separator(out)?;
+        }
+        file.last_printed = Some(line.number);
+
+        if self.with_file_name {
+            self.paint(out, FILE_NAME_COLOR, display_name(file.path))?;
+            self.paint(out, SEPARATOR_COLOR, separator)?;
+        }
+        if self.line_number {
+            self.paint(out, LINE_NUMBER_COLOR, line.number)?;
+            self.paint(out, SEPARATOR_COLOR, separator)?;
+        }
+        if self.byte_offset {
+            self.paint(out, LINE_NUMBER_COLOR, line.offset)?;
+            self.paint(out, SEPARATOR_COLOR, separator)?;
+        }
+        // Approximate matches also show how far off the closest match in the
+        // line is, e.g. ~1 for a single typo.
+        if self.distance && separator == ':' && !self.invert_match {
+            if let Some(distance) = line_distance(file.matcher, line.text) {
+                self.paint(out, LINE_NUMBER_COLOR, format_args!("~{distance}"))?;
+                self.paint(out, SEPARATOR_COLOR, separator)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Prints the text of a line with the spans highlighted, when coloring.
+    fn print_text(
+        &self,
+        out: &mut impl Write,
+        text: &str,
+        spans: impl Iterator<Item = Range<usize>>,
+    ) -> io::Result<()> {
+        if !self.color {
+            return writeln!(out, "{text}");
+        }
+        let mut end = 0;
+        for span in spans {
+            write!(out, "{}", &text[end..span.start])?;
+            if !span.is_empty() {
+                self.paint(out, MATCH_COLOR, &text[span.clone()])?;
+            }
+            end = span.end;
+        }
+        writeln!(out, "{}", &text[end..])
+    }
+
+    /// Prints the `--` line that separates groups of context lines.
+    pub fn print_separator(&self, out: &mut impl Write) -> io::Result<()> {
+        self.paint(out, SEPARATOR_COLOR, "--")?;
+        writeln!(out)
+    }
+
+    // Writes the value wrapped in the escapes for the color, when coloring.
+    fn paint(&self, out: &mut impl Write, color: &str, value: impl Display) -> io::Result<()> {
+        if self.color {
+            write!(out, "\x1b[{color}m{value}\x1b[0m")
+        } else {
+            write!(out, "{value}")
+        }
+    }
+
+    // Matching lines are printed with the spans of every match in them, while
+    // context lines (and the lines selected by --invert-match) have none.
+    fn print_json_line(
+        &self,
+        out: &mut impl Write,
+        file: &mut FileState,
+        line: &Line,
+        is_match: bool,
+    ) -> io::Result<()> {
+        if !is_match {
+            return self.print_json_record(out, file, line, line.number, None);
+        }
+        let spans: Vec<_> = if self.invert_match {
+            Vec::new()
+        } else {
+            find_iter(file.matcher, line.text).collect()
+        };
+        self.print_json_record(out, file, line, line.number, Some(&spans))
+    }
+
+    // A match record has the spans of the matches in it, and a context record
+    // has None. Records of multiline matches can cover several lines, which
+    // end_line_number says.
+    fn print_json_record(
+        &self,
+        out: &mut impl Write,
+        file: &mut FileState,
+        line: &Line,
+        end_line_number: usize,
+        spans: Option<&[Range<usize>]>,
+    ) -> io::Result<()> {
+        file.last_printed = Some(end_line_number);
+
+        let kind = if spans.is_some() { "match" } else { "context" };
+        write!(out, "{{\"type\":\"{kind}\",\"path\":")?;
+        json::write_string(out, &display_name(file.path))?;
+        write!(out, ",\"line_number\":{}", line.number)?;
+        if end_line_number != line.number {
+            write!(out, ",\"end_line_number\":{end_line_number}")?;
+        }
+        write!(out, ",\"byte_offset\":{},\"line\":", line.offset)?;
+        json::write_string(out, line.text)?;
+
+        write!(out, ",\"submatches\":[")?;
+        for (i, span) in spans.unwrap_or_default().iter().enumerate() {
+            if i > 0 {
+                write!(out, ",")?;
+            }
+            write!(out, "{{\"match\":")?;
+            json::write_string(out, &line.text[span.clone()])?;
+            write!(out, ",\"start\":{},\"end\":{}", span.start, span.end)?;
+            if let Some(distance) = file.matcher.distance(line.text, span.clone()) {
+                write!(out, ",\"distance\":{distance}")?;
+            }
+            write!(out, "}}")?;
+        }
+        writeln!(out, "]}}")
+    }
+}
+
+/// Returns the name to print for a path. A path of - is standard input.
+pub fn display_name(path: &Path) -> Cow<'_, str> {
+    if path == Path::new("-") {
+        Cow::Borrowed("(standard input)")
+    } else {
+        path.to_string_lossy()
+    }
+}
+
+// What the printer keeps track of while printing one file.
+struct FileState<'a> {
+    path: &'a Path,
+    matcher: &'a dyn Matcher,
+    last_printed: Option<usize>,
+    separate: bool,
+    // The number of matches in the matching lines, for --stats.
+    matches: usize,
+}
+
+// A copy of a line kept around in case it is needed as context, since the
+// line itself only lives until the next one is read.
+struct BufferedLine {
+    number: usize,
+    offset: usize,
+    text: String,
+}
+
+impl BufferedLine {
+    fn as_line(&self) -> Line<'_> {
+        Line {
+            number: self.number,
+            offset: self.offset,
+            text: &self.text,
+        }
+    }
+}
+
+/// Puts the number in front of the noun, which is singular only for one, like
+/// "1 match" but "0 matches".
+pub(crate) fn count(n: usize, one: &str, many: &str) -> String {
+    format!("{n} {}", if n == 1 { one } else { many })
+}
+
+// Returns the lowest edit distance of the matches in the line, or None for an
+// exact matcher.
+fn line_distance(matcher: &dyn Matcher, text: &str) -> Option<usize> {
+    let mut distances = find_iter(matcher, text).map(|span| matcher.distance(text, span));
+    let first = distances.next()??;
+    Some(distances.flatten().fold(first, usize::min))
+}
+
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Substring;
+
+    fn print(printer: &Printer, query: &str, contents: &str) -> String {
+        let mut out = Vec::new();
+        printer
+            .print_file(
+                &mut out,
+                Path::new("poem.txt"),
+                &mut contents.as_bytes(),
+                &Substring::new(query, false),
+                false,
+            )
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn json() {
+        let printer = Printer {
+            after_context: 1,
+            json: true,
+            ..Printer::default()
+        };
+        let mut out = Vec::new();
+        let summary = printer
+            .print_file(
+                &mut out,
+                Path::new("poem.txt"),
+                &mut "Rust\ntrust \"us\"\nme".as_bytes(),
+                &Substring::new("ust", false),
+                false,
+            )
+            .unwrap();
+        printer
+            .print_summary(&mut out, &[summary], Duration::ZERO)
+            .unwrap();
+
+        assert_eq!(
+            r#"{"type":"begin","path":"poem.txt"}
+{"type":"match","path":"poem.txt","line_number":1,"byte_offset":0,"line":"Rust","submatches":[{"match":"ust","start":1,"end":4}]}
+{"type":"match","path":"poem.txt","line_number":2,"byte_offset":5,"line":"trust \"us\"","submatches":[{"match":"ust","start":2,"end":5}]}
+{"type":"context","path":"poem.txt","line_number":3,"byte_offset":16,"line":"me","submatches":[]}
+{"type":"end","path":"poem.txt","matched_lines":2,"bytes_searched":18}
+{"type":"summary","files_searched":1,"files_matched":1,"matched_lines":2,"bytes_searched":18}
+"#,
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    const CONTENTS: &str = "\
+one
+two
+three
+four
+five
+six
+seven";
+
+    #[test]
+    fn line_numbers_and_offsets() {
+        let printer = Printer {
+            with_file_name: true,
+            line_number: true,
+            byte_offset: true,
+            ..Printer::default()
+        };
+
+        assert_eq!(
+            "poem.txt:2:4:two\npoem.txt:3:8:three\n",
+            print(&printer, "t", CONTENTS)
+        );
+    }
+
+    #[test]
+    fn context() {
+        let printer = Printer {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            ..Printer::default()
+        };
+
+        assert_eq!(
+            "1-a\n2:xb\n3-c\n--\n5-e\n6:xf\n7-g\n",
+            print(&printer, "x", "a\nxb\nc\nd\ne\nxf\ng\nh")
+        );
+        // Overlapping context is only printed once and needs no separator.
+        assert_eq!(
+            "1:xa\n2-b\n3:xc\n4-d\n",
+            print(&printer, "x", "xa\nb\nxc\nd\ne")
+        );
+    }
+
+    #[test]
+    fn modes() {
+        let mut printer = Printer {
+            with_file_name: true,
+            after_context: 1,
+            invert_match: true,
+            mode: OutputMode::Count,
+            ..Printer::default()
+        };
+        assert_eq!("poem.txt:3\n", print(&printer, "e", CONTENTS));
+
+        printer.mode = OutputMode::FilesWithMatches;
+        assert_eq!("poem.txt\n", print(&printer, "e", CONTENTS));
+        assert_eq!("", print(&printer, "", CONTENTS));
+
+        printer.mode = OutputMode::FilesWithoutMatch;
+        assert_eq!("", print(&printer, "e", CONTENTS));
+        assert_eq!("poem.txt\n", print(&printer, "", CONTENTS));
+
+        // The context after the last match is still printed.
+        printer.mode = OutputMode::Lines;
+        printer.invert_match = false;
+        printer.max_count = Some(1);
+        assert_eq!(
+            "poem.txt:two\npoem.txt-three\n",
+            print(&printer, "t", CONTENTS)
+        );
+    }
+
+    #[test]
+    fn invalid_utf8() {
+        let printer = Printer {
+            byte_offset: true,
+            ..Printer::default()
+        };
+        let mut out = Vec::new();
+        let mut contents: &[u8] = b"caf\xe9\r\nna\xefve caf\xc3\xa9\n";
+        printer
+            .print_file(
+                &mut out,
+                Path::new("-"),
+                &mut contents,
+                &Substring::new("caf", false),
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            "0:caf\u{FFFD}\n6:na\u{FFFD}ve café\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn color() {
+        let printer = Printer {
+            with_file_name: true,
+            line_number: true,
+            color: true,
+            ..Printer::default()
+        };
+
+        assert_eq!(
+            "\x1b[35mpoem.txt\x1b[0m\x1b[36m:\x1b[0m\x1b[32m3\x1b[0m\x1b[36m:\x1b[0m\
+             \x1b[1;31mth\x1b[0mree\n",
+            print(&printer, "th", CONTENTS)
+        );
+    }
+
+    #[test]
+    fn stats() {
+        let printer = Printer {
+            mode: OutputMode::FilesWithMatches,
+            stats: true,
+            ..Printer::default()
+        };
+        let mut out = Vec::new();
+        let mut summaries = Vec::new();
+        for (name, contents) in [("poem.txt", CONTENTS), ("empty.txt", "")] {
+            let summary = printer
+                .print_file(
+                    &mut out,
+                    Path::new(name),
+                    &mut contents.as_bytes(),
+                    &Substring::new("e", false),
+                    false,
+                )
+                .unwrap();
+            summaries.push(summary);
+        }
+        // The matches are all counted even though one is enough to print
+        // the file name.
+        assert_eq!(6, summaries[0].matches);
+        assert_eq!(4, summaries[0].matched_lines);
+
+        printer
+            .print_summary(&mut out, &summaries, Duration::from_millis(1500))
+            .unwrap();
+        assert_eq!(
+            "poem.txt\n\npoem.txt: 6 matches in 4 lines\n6 matches\n4 matched lines\n\
+             1 file contained matches\n2 files searched\n33 bytes searched\n\
+             1.500000 seconds elapsed\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!("1 match", count(1, "match", "matches"));
+        assert_eq!("0 matches", count(0, "match", "matches"));
+    }
+
+    #[test]
+    fn fuzzy_distance() {
+        let mut printer = Printer {
+            line_number: true,
+            distance: true,
+            ..Printer::default()
+        };
+        let mut out = Vec::new();
+        printer
+            .print_file(
+                &mut out,
+                Path::new("poem.txt"),
+                &mut "Rust\ntrst me\ntrust us".as_bytes(),
+                &crate::Fuzzy::new("trust", 1, false),
+                false,
+            )
+            .unwrap();
+
+        assert_eq!(
+            "2:~1:trst me\n3:~0:trust us\n",
+            String::from_utf8(out).unwrap()
+        );
+
+        printer.distance = false;
+        let mut out = Vec::new();
+        printer
+            .print_file(
+                &mut out,
+                Path::new("poem.txt"),
+                &mut "trst me".as_bytes(),
+                &crate::Fuzzy::new("trust", 1, false),
+                false,
+            )
+            .unwrap();
+        assert_eq!("1:trst me\n", String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn multiline() {
+        let mut printer = Printer {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            multiline: true,
+            ..Printer::default()
+        };
+        let matcher = crate::Regex::new(r"two\nthr|ive\ns", false).unwrap();
+        let print = |printer: &Printer| {
+            let mut out = Vec::new();
+            printer
+                .print_file(
+                    &mut out,
+                    Path::new("poem.txt"),
+                    &mut CONTENTS.as_bytes(),
+                    &matcher,
+                    false,
+                )
+                .unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            "1-one\n2:two\n3:three\n4-four\n5:five\n6:six\n7-seven\n",
+            print(&printer)
+        );
+
+        printer.color = true;
+        printer.line_number = false;
+        printer.before_context = 0;
+        printer.after_context = 0;
+        assert_eq!(
+            "\x1b[1;31mtwo\x1b[0m\n\x1b[1;31mthr\x1b[0mee\n\
+             f\x1b[1;31mive\x1b[0m\n\x1b[1;31ms\x1b[0mix\n",
+            print(&printer)
+        );
+
+        printer.color = false;
+        printer.json = true;
+        let json = print(&printer);
+        assert!(json.contains(
+            r#""line_number":2,"end_line_number":3,"byte_offset":4,"line":"two\nthree","submatches":[{"match":"two\nthr","start":0,"end":7}]"#
+        ));
+        assert!(json.contains(r#""matched_lines":4,"bytes_searched":33"#));
+    }
+}
@@ -0,0 +1,895 @@
+use std::{error::Error, fmt, ops::Range};
+
+use crate::matcher::{fold_case, Matcher};
+
+// A small regular expression engine. The pattern is parsed into a syntax tree,
+// compiled into a list of instructions, and then run with a Pike VM. The VM
+// walks the haystack once and tracks every possible position in the program at
+// the same time, so matching is linear in the length of the haystack (there is
+// no backtracking to blow up on patterns like `(a*)*b`).
+//
+// Supported syntax:
+//
+//   .            any char except a newline
+//   [abc] [^a-z] character classes, which may contain \d \w \s
+//   \d \w \s     digit, word and whitespace chars (and \D \W \S negated)
+//   ^ $          start and end of a line
+//   \b \B        word boundary and not a word boundary
+//   a|b          alternation
+//   (a) (?:a)    capturing and non-capturing groups
+//   * + ? {n,m}  repetition, followed by ? to make it lazy
+pub struct Regex {
+    program: Vec<Inst>,
+    // Each capture group has a start and end slot. Group 0 is the whole match.
+    slots: usize,
+    ignore_case: bool,
+}
+
+#[derive(Debug)]
+pub struct RegexError {
+    pattern: String,
+    message: String,
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid regex `{}`: {}", self.pattern, self.message)
+    }
+}
+
+impl Error for RegexError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+    StartLine,
+    EndLine,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    // The bool indicates whether the item is negated, e.g. \D.
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    // The index is None for non-capturing groups.
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    // Try the first branch before the second one.
+    Split(usize, usize),
+    Jmp(usize),
+    Save(usize),
+    Match,
+}
+
+// Counted repetitions are expanded when compiling, so a single one can't go
+// past this, which keeps the error for `a{1000000}` about the count.
+const MAX_REPEAT: u32 = 1000;
+// Nested repetitions multiply, so `((a{1000}){1000}){1000}` would still expand
+// to an enormous program. The size is worked out before compiling, and
+// patterns over this many instructions are refused.
+const MAX_PROGRAM: usize = 100_000;
+// The parser and compiler recurse into groups, so a pattern that is nothing
+// but thousands of nested parentheses would overflow the stack. Nobody nests
+// groups anywhere near this deep on purpose.
+const MAX_NESTING: usize = 100;
+
+impl Regex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+            depth: 0,
+            ignore_case,
+        };
+        let node = parser.parse().map_err(|message| RegexError {
+            pattern: pattern.to_string(),
+            message,
+        })?;
+
+        if program_len(&node) > MAX_PROGRAM {
+            return Err(RegexError {
+                pattern: pattern.to_string(),
+                message: format!("regex too large, it compiles to over {MAX_PROGRAM} instructions"),
+            });
+        }
+
+        let mut program = Vec::new();
+        // Wrap the whole pattern in group 0 so the VM records the match bounds.
+        program.push(Inst::Save(0));
+        compile(&node, &mut program);
+        program.push(Inst::Save(1));
+        program.push(Inst::Match);
+
+        Ok(Regex {
+            program,
+            slots: (parser.groups + 1) * 2,
+            ignore_case,
+        })
+    }
+
+    /// Returns the number of capture groups including the implicit group 0.
+    pub fn captures_len(&self) -> usize {
+        self.slots / 2
+    }
+
+    fn exec(&self, text: &str, start: usize) -> Option<Vec<Option<usize>>> {
+        let mut current = Threads::new(self.program.len());
+        let mut next = Threads::new(self.program.len());
+        let mut matched = None;
+        let mut pos = start;
+
+        loop {
+            // Only start new threads while nothing has matched yet, this is
+            // what makes the search return the leftmost match. The new thread
+            // goes last so it has the lowest priority.
+            if matched.is_none() {
+                let mut slots = vec![None; self.slots];
+                self.add_thread(&mut current, 0, pos, text, &mut slots);
+            }
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+
+            let c = text[pos..].chars().next();
+            for thread in current.list.iter_mut() {
+                let consumed = match (&self.program[thread.pc], c) {
+                    (Inst::Match, _) => {
+                        // Every thread after this one has a lower priority, so
+                        // they can be dropped.
+                        matched = Some(std::mem::take(&mut thread.slots));
+                        break;
+                    }
+                    (Inst::Char(expected), Some(c)) => self.char_eq(*expected, c),
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => self.class_matches(class, c),
+                    _ => false,
+                };
+                if let (true, Some(c)) = (consumed, c) {
+                    self.add_thread(
+                        &mut next,
+                        thread.pc + 1,
+                        pos + c.len_utf8(),
+                        text,
+                        &mut thread.slots,
+                    );
+                }
+            }
+            current.clear();
+
+            match c {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+            std::mem::swap(&mut current, &mut next);
+        }
+
+        matched
+    }
+
+    // Follows the instructions that don't consume any input and adds the
+    // threads that end up on an instruction that does. A program can chain
+    // tens of thousands of these, like `(?:\b){1000}`, so rather than
+    // recursing the steps left to take are kept on a stack. Taking them in
+    // the order recursion would keeps the threads in priority order.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        pos: usize,
+        text: &str,
+        slots: &mut [Option<usize>],
+    ) {
+        threads.stack.push(Step::Follow(pc));
+        while let Some(step) = threads.stack.pop() {
+            let pc = match step {
+                Step::Follow(pc) => pc,
+                Step::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+            };
+            if threads.visited[pc] {
+                continue;
+            }
+            threads.visited[pc] = true;
+
+            match &self.program[pc] {
+                Inst::Jmp(target) => threads.stack.push(Step::Follow(*target)),
+                Inst::Split(first, second) => {
+                    // The first branch is taken before the second, so it
+                    // goes on the stack last.
+                    threads.stack.push(Step::Follow(*second));
+                    threads.stack.push(Step::Follow(*first));
+                }
+                Inst::Save(slot) => {
+                    // The slot only has the position for the threads that
+                    // go through here, so the old value is put back after.
+                    threads.stack.push(Step::Restore(*slot, slots[*slot]));
+                    slots[*slot] = Some(pos);
+                    threads.stack.push(Step::Follow(pc + 1));
+                }
+                Inst::Assert(assertion) => {
+                    if assertion_holds(*assertion, text, pos) {
+                        threads.stack.push(Step::Follow(pc + 1));
+                    }
+                }
+                _ => threads.list.push(Thread {
+                    pc,
+                    slots: slots.to_vec(),
+                }),
+            }
+        }
+    }
+
+    fn char_eq(&self, expected: char, c: char) -> bool {
+        expected == c || (self.ignore_case && fold_case(expected).eq(fold_case(c)))
+    }
+
+    fn class_matches(&self, class: &Class, c: char) -> bool {
+        let found = if self.ignore_case {
+            // Check the char as written as well as its other cases, so that
+            // [a-z] matches 'Q' when ignoring case.
+            std::iter::once(c)
+                .chain(c.to_lowercase())
+                .chain(c.to_uppercase())
+                .chain(fold_case(c))
+                .any(|c| class.contains(c))
+        } else {
+            class.contains(c)
+        };
+        found != class.negated
+    }
+}
+
+impl Matcher for Regex {
+    fn find_at(&self, haystack: &str, start: usize) -> Option<Range<usize>> {
+        match self.exec(haystack, start)?[..] {
+            [Some(s), Some(e), ..] => Some(s..e),
+            _ => None,
+        }
+    }
+
+    fn captures_at(&self, haystack: &str, start: usize) -> Option<Vec<Option<Range<usize>>>> {
+        let slots = self.exec(haystack, start)?;
+        Some(
+            slots
+                .chunks(2)
+                .map(|pair| match pair {
+                    [Some(s), Some(e)] => Some(*s..*e),
+                    _ => None,
+                })
+                .collect(),
+        )
+    }
+}
+
+impl Class {
+    fn contains(&self, c: char) -> bool {
+        self.items.iter().any(|item| match *item {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word_char(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        })
+    }
+}
+
+struct Thread {
+    pc: usize,
+    slots: Vec<Option<usize>>,
+}
+
+// The threads for one position in the haystack. The visited flags make sure
+// each instruction is only added once, which both removes duplicate work and
+// stops empty loops like `(a*)*` from recursing forever.
+struct Threads {
+    list: Vec<Thread>,
+    visited: Vec<bool>,
+    // The steps left for add_thread, kept here so it doesn't allocate a
+    // stack every time.
+    stack: Vec<Step>,
+}
+
+// A step add_thread has yet to take.
+enum Step {
+    // Follow the instruction at the pc.
+    Follow(usize),
+    // Put the slot back to the value it had before a Save.
+    Restore(usize, Option<usize>),
+}
+
+impl Threads {
+    fn new(len: usize) -> Threads {
+        Threads {
+            list: Vec::new(),
+            visited: vec![false; len],
+            stack: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.visited.iter_mut().for_each(|v| *v = false);
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn assertion_holds(assertion: Assertion, text: &str, pos: usize) -> bool {
+    let before = text[..pos].chars().next_back();
+    let after = text[pos..].chars().next();
+    match assertion {
+        Assertion::StartLine => before.is_none_or(|c| c == '\n'),
+        Assertion::EndLine => after.is_none_or(|c| c == '\n'),
+        Assertion::WordBoundary | Assertion::NotWordBoundary => {
+            let boundary = before.is_some_and(is_word_char) != after.is_some_and(is_word_char);
+            boundary == (assertion == Assertion::WordBoundary)
+        }
+    }
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+    match node {
+        Node::Empty => {}
+        Node::Char(c) => program.push(Inst::Char(*c)),
+        Node::Any => program.push(Inst::Any),
+        Node::Class(class) => program.push(Inst::Class(class.clone())),
+        Node::Assert(assertion) => program.push(Inst::Assert(*assertion)),
+        Node::Group(node, index) => match index {
+            Some(index) => {
+                program.push(Inst::Save(index * 2));
+                compile(node, program);
+                program.push(Inst::Save(index * 2 + 1));
+            }
+            None => compile(node, program),
+        },
+        Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+        Node::Alternate(nodes) => {
+            // Each branch but the last is tried with a split, and every branch
+            // jumps to the end once it matched.
+            let mut jumps = Vec::new();
+            for (i, node) in nodes.iter().enumerate() {
+                if i + 1 < nodes.len() {
+                    let split = program.len();
+                    program.push(Inst::Split(split + 1, 0));
+                    compile(node, program);
+                    jumps.push(program.len());
+                    program.push(Inst::Jmp(0));
+                    let next = program.len();
+                    program[split] = Inst::Split(split + 1, next);
+                } else {
+                    compile(node, program);
+                }
+            }
+            let end = program.len();
+            for jump in jumps {
+                program[jump] = Inst::Jmp(end);
+            }
+        }
+        Node::Repeat {
+            node,
+            min,
+            max,
+            greedy,
+        } => {
+            for _ in 0..*min {
+                compile(node, program);
+            }
+            match max {
+                None => {
+                    // loop: split body, end; body; jmp loop; end:
+                    let split = program.len();
+                    program.push(Inst::Split(0, 0));
+                    compile(node, program);
+                    program.push(Inst::Jmp(split));
+                    let end = program.len();
+                    program[split] = branch(split + 1, end, *greedy);
+                }
+                Some(max) => {
+                    // Every optional copy can skip straight to the end.
+                    let mut splits = Vec::new();
+                    for _ in *min..*max {
+                        splits.push(program.len());
+                        program.push(Inst::Split(0, 0));
+                        compile(node, program);
+                    }
+                    let end = program.len();
+                    for split in splits {
+                        program[split] = branch(split + 1, end, *greedy);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Returns the number of instructions `compile` would emit for the node. The
+// arithmetic saturates, since the point is to catch sizes that don't fit.
+fn program_len(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Char(_) | Node::Any | Node::Class(_) | Node::Assert(_) => 1,
+        Node::Group(node, index) => {
+            program_len(node).saturating_add(if index.is_some() { 2 } else { 0 })
+        }
+        Node::Concat(nodes) => nodes
+            .iter()
+            .fold(0usize, |len, node| len.saturating_add(program_len(node))),
+        // Every branch but the last has a split and a jump.
+        Node::Alternate(nodes) => nodes
+            .iter()
+            .fold(0usize, |len, node| {
+                len.saturating_add(program_len(node)).saturating_add(2)
+            })
+            .saturating_sub(2),
+        Node::Repeat { node, min, max, .. } => {
+            let body = program_len(node);
+            let copies = body.saturating_mul(*min as usize);
+            match max {
+                // A split, the body and a jump back.
+                None => copies.saturating_add(body).saturating_add(2),
+                // A split and the body for every optional copy.
+                Some(max) => copies
+                    .saturating_add(body.saturating_add(1).saturating_mul((max - min) as usize)),
+            }
+        }
+    }
+}
+
+fn branch(body: usize, skip: usize, greedy: bool) -> Inst {
+    if greedy {
+        Inst::Split(body, skip)
+    } else {
+        Inst::Split(skip, body)
+    }
+}
+
+// A recursive descent parser. Alternation binds loosest, then concatenation,
+// then repetition.
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+    // The number of groups the parser is inside of.
+    depth: usize,
+    ignore_case: bool,
+}
+
+impl Parser {
+    fn parse(&mut self) -> Result<Node, String> {
+        let node = self.parse_alternation()?;
+        match self.peek() {
+            None => Ok(node),
+            Some(c) => Err(format!("unexpected `{c}` at position {}", self.pos)),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, String> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeat(atom)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    // Like Perl and Go, a repetition can't itself be repeated, as in `a**`.
+    // Besides meaning nothing more than a single one, every extra operator
+    // would nest the node one deeper, with no group to count against
+    // MAX_NESTING.
+    fn parse_repeat(&mut self, node: Node) -> Result<Node, String> {
+        let Some((min, max)) = self.parse_repetition()? else {
+            return Ok(node);
+        };
+        // Step over the operator, or the closing brace of a counted one.
+        self.pos += 1;
+        let greedy = !self.eat('?');
+        if self.parse_repetition()?.is_some() {
+            return Err(format!(
+                "nested repetition operator at position {}",
+                self.pos
+            ));
+        }
+        Ok(Node::Repeat {
+            node: Box::new(node),
+            min,
+            max,
+            greedy,
+        })
+    }
+
+    // Returns the bounds of the repetition operator at the position, if there
+    // is one. The position is left on it.
+    fn parse_repetition(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        Ok(match self.peek() {
+            Some('*') => Some((0, None)),
+            Some('+') => Some((1, None)),
+            Some('?') => Some((0, Some(1))),
+            Some('{') => self.parse_counted()?,
+            _ => None,
+        })
+    }
+
+    // Parses `{n}`, `{n,}` or `{n,m}`. The position is left on the closing
+    // brace. Returns None when the brace doesn't start a valid repetition, in
+    // which case it is treated as a literal.
+    fn parse_counted(&mut self) -> Result<Option<(u32, Option<u32>)>, String> {
+        let start = self.pos;
+        self.pos += 1;
+        let min = self.parse_number();
+        let max = if self.eat(',') {
+            if self.peek() == Some('}') {
+                None
+            } else {
+                match self.parse_number() {
+                    Some(max) => Some(max),
+                    None => {
+                        self.pos = start;
+                        return Ok(None);
+                    }
+                }
+            }
+        } else {
+            min
+        };
+        let min = match (min, self.peek()) {
+            (Some(min), Some('}')) => min,
+            _ => {
+                self.pos = start;
+                return Ok(None);
+            }
+        };
+        if max.is_some_and(|max| max < min) {
+            return Err(format!("invalid repetition {{{min},{}}}", max.unwrap()));
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(format!("repetition count exceeds {MAX_REPEAT}"));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, String> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Assert(Assertion::StartLine)),
+            '$' => Ok(Node::Assert(Assertion::EndLine)),
+            '[' => self.parse_class().map(Node::Class),
+            '\\' => self.parse_escape(),
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(format!("unsupported group syntax at position {}", self.pos));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                if self.depth == MAX_NESTING {
+                    return Err(format!("groups nested more than {MAX_NESTING} deep"));
+                }
+                self.depth += 1;
+                let node = self.parse_alternation()?;
+                self.depth -= 1;
+                if !self.eat(')') {
+                    return Err("unclosed group".to_string());
+                }
+                Ok(Node::Group(Box::new(node), index))
+            }
+            '*' | '+' | '?' => Err(format!(
+                "repetition operator `{c}` at position {} has nothing to repeat",
+                self.pos - 1
+            )),
+            c => Ok(self.literal(c)),
+        }
+    }
+
+    // Chars are compared by their case folded form when ignoring case, but
+    // that only works one char at a time. A char like 'ß' that folds to
+    // several chars also gets a branch matching its folded form, so "ß" can
+    // match "SS" (the other way around isn't supported).
+    fn literal(&self, c: char) -> Node {
+        let folded: Vec<char> = fold_case(c).collect();
+        if !self.ignore_case || folded.len() == 1 {
+            return Node::Char(c);
+        }
+        Node::Alternate(vec![
+            Node::Char(c),
+            Node::Concat(folded.into_iter().map(Node::Char).collect()),
+        ])
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, String> {
+        let c = self
+            .peek()
+            .ok_or("pattern ends with a trailing backslash")?;
+        self.pos += 1;
+        let class = |item| {
+            Node::Class(Class {
+                items: vec![item],
+                negated: false,
+            })
+        };
+        Ok(match c {
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            _ => match escape_class_item(c)? {
+                Ok(item) => class(item),
+                Err(c) => self.literal(c),
+            },
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, String> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or("unclosed character class")?;
+            self.pos += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let lo = if c == '\\' {
+                let c = self.peek().ok_or("unclosed character class")?;
+                self.pos += 1;
+                match escape_class_item(c)? {
+                    Ok(item) => {
+                        items.push(item);
+                        continue;
+                    }
+                    Err(c) => c,
+                }
+            } else {
+                c
+            };
+
+            // A dash makes a range unless it is the last char in the class.
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&c| c != ']') {
+                self.pos += 1;
+                let mut hi = self.peek().unwrap();
+                self.pos += 1;
+                if hi == '\\' {
+                    let c = self.peek().ok_or("unclosed character class")?;
+                    self.pos += 1;
+                    hi = match escape_class_item(c)? {
+                        Ok(_) => return Err("invalid range in character class".to_string()),
+                        Err(c) => c,
+                    };
+                }
+                if hi < lo {
+                    return Err(format!("invalid range {lo}-{hi} in character class"));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Range(lo, lo));
+            }
+        }
+        Ok(Class { items, negated })
+    }
+}
+
+// Resolves the char after a backslash. It is either a class like \d, or a
+// single (possibly special) char.
+fn escape_class_item(c: char) -> Result<Result<ClassItem, char>, String> {
+    Ok(match c {
+        'd' => Ok(ClassItem::Digit(false)),
+        'D' => Ok(ClassItem::Digit(true)),
+        'w' => Ok(ClassItem::Word(false)),
+        'W' => Ok(ClassItem::Word(true)),
+        's' => Ok(ClassItem::Space(false)),
+        'S' => Ok(ClassItem::Space(true)),
+        'n' => Err('\n'),
+        't' => Err('\t'),
+        'r' => Err('\r'),
+        'f' => Err('\x0c'),
+        'v' => Err('\x0b'),
+        '0' => Err('\0'),
+        c if c.is_ascii_alphanumeric() => return Err(format!("unrecognized escape `\\{c}`")),
+        c => Err(c),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, haystack: &str) -> Option<Range<usize>> {
+        Regex::new(pattern, false).unwrap().find_at(haystack, 0)
+    }
+
+    #[test]
+    fn literals_and_classes() {
+        assert_eq!(Some(2..5), find("c[a-z]t", "a cat"));
+        assert_eq!(Some(4..7), find(r"\d+", "abc 123 def"));
+        assert_eq!(Some(0..1), find("[^a-z]", "Abc"));
+        assert_eq!(None, find(r"a\.b", "axb"));
+    }
+
+    #[test]
+    fn anchors_and_boundaries() {
+        assert_eq!(Some(0..4), find("^Rust", "Rust: Trust"));
+        assert_eq!(None, find("^rust$", "trust"));
+        assert_eq!(Some(6..11), find(r"\bTrust\b", "Rust: Trust me"));
+        assert_eq!(None, find(r"\brust", "trust"));
+    }
+
+    #[test]
+    fn alternation_and_repetition() {
+        assert_eq!(Some(4..7), find("dog|cat", "the cat"));
+        assert_eq!(Some(0..6), find("(ab)+", "ababab"));
+        assert_eq!(Some(0..2), find("(ab)+?", "ababab"));
+        assert_eq!(Some(0..3), find("a{2,3}", "aaaa"));
+        assert_eq!(Some(0..2), find("a{2}", "aaaa"));
+        assert_eq!(Some(1..6), find("x{,2}", "ax{,2}"));
+        assert_eq!(Some(0..11), find("(a*)*b", "aaaaaaaaaab"));
+    }
+
+    #[test]
+    fn leftmost_first() {
+        assert_eq!(Some(0..3), find("abc|ab", "abcd"));
+        assert_eq!(Some(0..2), find("ab|abc", "abcd"));
+    }
+
+    #[test]
+    fn ignore_case() {
+        let regex = Regex::new("r[a-z]st", true).unwrap();
+        assert_eq!(Some(0..4), regex.find_at("RUST", 0));
+
+        let regex = Regex::new("stra(ß|x)e", true).unwrap();
+        assert_eq!(Some(4..11), regex.find_at("Die STRASSE", 0));
+        assert_eq!(Some(4..12), regex.find_at("Die Straẞe", 0));
+        let regex = Regex::new("ſ[a-z]", true).unwrap();
+        assert_eq!(Some(0..2), regex.find_at("SK", 0));
+    }
+
+    #[test]
+    fn captures() {
+        let regex = Regex::new(r"(\w+)@(\w+)?", false).unwrap();
+        assert_eq!(3, regex.captures_len());
+        assert_eq!(
+            Some(vec![Some(3..10), Some(3..9), None]),
+            regex.captures_at("to ferris@", 0)
+        );
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        for pattern in ["(a", "a)", "*a", "[a", r"\q", "a{3,2}", "a\\"] {
+            assert!(Regex::new(pattern, false).is_err(), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn program_size_limit() {
+        // The size is worked out without compiling, so it has to agree.
+        for pattern in ["a|bc|", "(ab)*?c+", "(?:a{2,4}|[xy]){3}", r"^\w{0,2}$"] {
+            let regex = Regex::new(pattern, false).unwrap();
+            let mut parser = Parser {
+                chars: pattern.chars().collect(),
+                pos: 0,
+                groups: 0,
+                depth: 0,
+                ignore_case: false,
+            };
+            let len = program_len(&parser.parse().unwrap());
+            assert_eq!(regex.program.len(), len + 3, "{pattern}");
+        }
+
+        // Each repetition is within the limit, but together they aren't.
+        match Regex::new("((a{1000}){1000}){1000}", false) {
+            Err(e) => assert!(e.to_string().contains("regex too large")),
+            Ok(_) => panic!("expected the regex to be too large"),
+        }
+        assert!(Regex::new("(a{1000}){100}", false).is_err());
+        assert!(Regex::new("(a{100}){100}", false).is_ok());
+    }
+
+    #[test]
+    fn long_chains_of_empty_matches() {
+        // 90 000 assertions in a row, which add_thread follows without
+        // recursing.
+        let regex = Regex::new(r"(?:(?:(?:\b){100}){100}){9}x", false).unwrap();
+        assert_eq!(Some(4..5), regex.find_at("abc x", 0));
+        let regex = Regex::new(r"(?:(?:(?:a?){100}){100}){4}b", false).unwrap();
+        assert_eq!(Some(0..4), regex.find_at("aaab", 0));
+    }
+
+    #[test]
+    fn nesting_limit() {
+        let nested = |depth| format!("{}a{}", "(".repeat(depth), ")".repeat(depth));
+        assert!(Regex::new(&nested(MAX_NESTING), false).is_ok());
+        match Regex::new(&nested(50_000), false) {
+            Err(e) => assert!(e.to_string().contains("nested more than")),
+            Ok(_) => panic!("expected the groups to be nested too deep"),
+        }
+        // Stacked repetitions would nest just as deep without any groups.
+        assert!(Regex::new(&format!("a{}", "*".repeat(50_000)), false).is_err());
+        assert!(Regex::new("a{2}{3}", false).is_err());
+        assert!(Regex::new("a*?b+?", false).is_ok());
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    borrow::Cow,
+    fs::{self, File},
+    io::{self, Read, Write},
+    ops::Range,
+    path::Path,
+    process,
+};
+
+use crate::{
+    error::{read_error, FileReader},
+    matcher::{find_iter, Matcher},
+    printer::display_name,
+    walk,
+};
+
+// The number of unchanged lines shown around each change in the diff, which is
+// the same as diff -u.
+const DIFF_CONTEXT: usize = 3;
+
+/// Replaces every match in `line` with `replacement`. In the replacement, `$N`
+/// and `${N}` stand for capture group N (group 0 is the whole match) and `$$`
+/// stands for a literal `$`. Groups that didn't take part in the match are
+/// replaced with nothing.
+pub fn replace_all<'a>(matcher: &dyn Matcher, line: &'a str, replacement: &str) -> Cow<'a, str> {
+    let mut result = String::new();
+    let mut last = 0;
+    let mut replaced = false;
+    for found in find_iter(matcher, line) {
+        // The leftmost match at the start of this one is this one, so asking
+        // for its groups gives the groups of this match.
+        let Some(groups) = matcher.captures_at(line, found.start) else {
+            continue;
+        };
+        result.push_str(&line[last..found.start]);
+        expand(replacement, line, &groups, &mut result);
+        last = found.end;
+        replaced = true;
+    }
+
+    if !replaced {
+        return Cow::Borrowed(line);
+    }
+    result.push_str(&line[last..]);
+    Cow::Owned(result)
+}
+
+fn expand(replacement: &str, line: &str, groups: &[Option<Range<usize>>], out: &mut String) {
+    let mut rest = replacement;
+    while let Some(i) = rest.find('$') {
+        out.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        }
+
+        let (digits, after) = match rest.strip_prefix('{') {
+            Some(braced) => match braced.split_once('}') {
+                Some(split) => split,
+                None => ("", rest),
+            },
+            None => {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                rest.split_at(end)
+            }
+        };
+        match digits.parse::<usize>() {
+            Ok(group) => {
+                if let Some(Some(range)) = groups.get(group) {
+                    out.push_str(&line[range.clone()]);
+                }
+                rest = after;
+            }
+            // A $ that isn't followed by a group is kept as it is.
+            Err(_) => out.push('$'),
+        }
+    }
+    out.push_str(rest);
+}
+
+/// Replaces the matches in one file. Without `write` the changes are printed
+/// as a unified diff and the file is left alone. With it the file is replaced
+/// with the new contents, or for standard input the new contents are printed.
+///
+/// Binary files are skipped, since writing them back out could mangle them.
+/// So are files that aren't UTF-8, but those are reported as an error reading
+/// the file, since they would have been searched otherwise.
+pub(crate) fn replace_file(
+    out: &mut impl Write,
+    path: &Path,
+    matcher: &dyn Matcher,
+    replacement: &str,
+    write: bool,
+) -> io::Result<bool> {
+    let mut contents = Vec::new();
+    if path == Path::new("-") {
+        FileReader(io::stdin().lock()).read_to_end(&mut contents)?;
+    } else {
+        FileReader(File::open(path).map_err(read_error)?).read_to_end(&mut contents)?;
+    }
+    if walk::is_binary(&contents) {
+        return Ok(false);
+    }
+    let contents = String::from_utf8(contents).map_err(|_| {
+        read_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not valid UTF-8, so it was left alone",
+        ))
+    })?;
+
+    let old: Vec<&str> = contents.split_inclusive('\n').collect();
+    let new: Vec<Cow<str>> = old
+        .iter()
+        .map(|&line| {
+            let text = line.strip_suffix('\n').unwrap_or(line);
+            let text = text.strip_suffix('\r').unwrap_or(text);
+            match replace_all(matcher, text, replacement) {
+                Cow::Borrowed(_) => Cow::Borrowed(line),
+                Cow::Owned(mut replaced) => {
+                    replaced.push_str(&line[text.len()..]);
+                    Cow::Owned(replaced)
+                }
+            }
+        })
+        .collect();
+
+    let changed = new.iter().any(|line| matches!(line, Cow::Owned(_)));
+    if write {
+        let new = new.concat();
+        if path == Path::new("-") {
+            out.write_all(new.as_bytes())?;
+        } else if changed {
+            write_atomically(path, &new).map_err(|e| {
+                io::Error::new(e.kind(), format!("couldn't write {}: {e}", path.display()))
+            })?;
+        }
+    } else {
+        print_diff(out, path, &old, &new)?;
+    }
+    Ok(changed)
+}
+
+// Writes the new contents to a temporary file next to the original and then
+// renames it over the original. A rename within a directory is atomic, so
+// anyone reading the file sees either the old contents or the new ones, and
+// an error part way through leaves the original untouched.
+fn write_atomically(path: &Path, contents: &str) -> io::Result<()> {
+    // Write through symlinks rather than replacing them with a file.
+    let path = fs::canonicalize(path)?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp = path.with_file_name(format!(".{name}.minigrep-{}", process::id()));
+
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.set_permissions(fs::metadata(&path)?.permissions())?;
+        file.sync_all()?;
+        fs::rename(&temp, &path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// Prints the changes as a unified diff. A replacement can contain newlines, so
+// one old line can turn into several new ones.
+fn print_diff(out: &mut impl Write, path: &Path, old: &[&str], new: &[Cow<str>]) -> io::Result<()> {
+    let changed: Vec<usize> = (0..old.len()).filter(|&i| old[i] != new[i]).collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    // Changes whose context would overlap are shown in the same hunk.
+    let mut hunks: Vec<Range<usize>> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(DIFF_CONTEXT);
+        let end = (i + 1 + DIFF_CONTEXT).min(old.len());
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.end => hunk.end = end,
+            _ => hunks.push(start..end),
+        }
+    }
+
+    let name = display_name(path);
+    writeln!(out, "--- {name}")?;
+    writeln!(out, "+++ {name}")?;
+
+    let new_len = |lines: &[Cow<str>]| -> usize {
+        lines
+            .iter()
+            .map(|line| line.split_inclusive('\n').count())
+            .sum()
+    };
+    for hunk in hunks {
+        let old_start = hunk.start + 1;
+        let new_start = new_len(&new[..hunk.start]) + 1;
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, hunk.len()),
+            hunk_range(new_start, new_len(&new[hunk.clone()]))
+        )?;
+
+        // Like diff, the old lines of a run of changes come before the new
+        // ones.
+        let mut i = hunk.start;
+        while i < hunk.end {
+            if old[i] == new[i] {
+                diff_line(out, ' ', old[i])?;
+                i += 1;
+                continue;
+            }
+            let run_end = (i..hunk.end)
+                .find(|&j| old[j] == new[j])
+                .unwrap_or(hunk.end);
+            for line in &old[i..run_end] {
+                diff_line(out, '-', line)?;
+            }
+            for line in new[i..run_end]
+                .iter()
+                .flat_map(|line| line.split_inclusive('\n'))
+            {
+                diff_line(out, '+', line)?;
+            }
+            i = run_end;
+        }
+    }
+    Ok(())
+}
+
+// A range in a hunk header. An empty range is written with the line before it.
+fn hunk_range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{},0", start - 1),
+        1 => start.to_string(),
+        _ => format!("{start},{len}"),
+    }
+}
+
+fn diff_line(out: &mut impl Write, prefix: char, line: &str) -> io::Result<()> {
+    match line.strip_suffix('\n') {
+        Some(line) => writeln!(out, "{prefix}{line}"),
+        None => {
+            writeln!(out, "{prefix}{line}")?;
+            writeln!(out, "\\ No newline at end of file")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::{Regex, Substring};
+
+    fn replace(pattern: &str, line: &str, replacement: &str) -> String {
+        let regex = Regex::new(pattern, false).unwrap();
+        replace_all(&regex, line, replacement).into_owned()
+    }
+
+    #[test]
+    fn capture_references() {
+        assert_eq!(
+            "ferris at crab.rs",
+            replace(r"(\w+)@(\w+)", "crab@ferris.rs", "$2 at ${1}")
+        );
+        assert_eq!("$1 costs $$", replace(r"(\d+)", "5 costs $$", "$$1"));
+        assert_eq!("a-b-yc", replace("x(y)?", "axbxyc", "-$1$9"));
+        assert_eq!("${x}", replace("x", "x", "${x}"));
+        assert!(matches!(
+            replace_all(&Substring::new("z", false), "abc", "y"),
+            Cow::Borrowed("abc")
+        ));
+    }
+
+    #[test]
+    fn diff() {
+        let old = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten";
+        let old: Vec<&str> = old.split_inclusive('\n').collect();
+        let mut new: Vec<Cow<str>> = old.iter().map(|&line| Cow::Borrowed(line)).collect();
+        new[1] = Cow::Borrowed("2\nTWO\n");
+        new[9] = Cow::Borrowed("10");
+
+        let mut out = Vec::new();
+        print_diff(&mut out, Path::new("numbers.txt"), &old, &new).unwrap();
+        assert_eq!(
+            "\
+--- numbers.txt
++++ numbers.txt
+@@ -1,5 +1,6 @@
+ one
+-two
++2
++TWO
+ three
+ four
+ five
+@@ -7,4 +8,4 @@
+ seven
+ eight
+ nine
+-ten
+\\ No newline at end of file
++10
+\\ No newline at end of file
+",
+            String::from_utf8(out).unwrap()
+        );
+    }
+
+    #[test]
+    fn write_in_place() {
+        let dir = TempDir::new("replace");
+        let path = dir.join("poem.txt");
+        fs::write(&path, "I'm nobody!\r\nWho are you?\n").unwrap();
+
+        let matcher = Regex::new("(no)body", false).unwrap();
+        let mut out = Vec::new();
+        replace_file(&mut out, &path, &matcher, "${1}one", false).unwrap();
+        assert_eq!(
+            "I'm nobody!\r\nWho are you?\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        assert!(!out.is_empty());
+
+        replace_file(&mut out, &path, &matcher, "${1}one", true).unwrap();
+        assert_eq!(
+            "I'm noone!\r\nWho are you?\n",
+            fs::read_to_string(&path).unwrap()
+        );
+        // Only the file itself is left in the directory.
+        assert_eq!(1, fs::read_dir(&*dir).unwrap().count());
+
+        let latin1 = dir.join("latin1.txt");
+        fs::write(&latin1, b"no caf\xe9 for nobody\n").unwrap();
+        let e = replace_file(&mut out, &latin1, &matcher, "${1}one", true).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, e.kind());
+        assert_eq!(
+            b"no caf\xe9 for nobody\n".as_slice(),
+            fs::read(&latin1).unwrap()
+        );
+    }
+}
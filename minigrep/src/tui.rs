@@ -0,0 +1,604 @@
+use std::{
+    collections::HashSet,
+    env,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, Read, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    decompress,
+    printer::{
+        count, display_name, FILE_NAME_COLOR, LINE_NUMBER_COLOR, MATCH_COLOR, SEPARATOR_COLOR,
+    },
+    search, walk, Config, Fuzzy, MinigrepError, Walker,
+};
+
+// Searching stops after this many matching lines, so typing stays responsive
+// even when the first letters of a query match everywhere.
+const MAX_HITS: usize = 1000;
+
+// The number of lines shown before and after the selected match.
+const PREVIEW_CONTEXT: usize = 3;
+
+/// Runs the interactive result browser: the query is edited at the top of
+/// the screen and the results are searched again after every key.
+///
+/// The terminal is put in raw mode with `stty`, and drawn with ANSI escapes
+/// on the alternate screen, so whatever was on the screen before comes back
+/// afterwards. The screen is redrawn after each key, which is also when a
+/// change in the terminal size is noticed.
+pub fn interactive(config: Config) -> Result<(), MinigrepError> {
+    let walker = Walker {
+        hidden: config.hidden,
+        ignore: !config.no_ignore,
+    };
+    // There is nowhere to show errors once the screen is taken over, so
+    // directories that can't be read are left out, like files that can't be
+    // read are when searching.
+    let (files, _) = walker.walk(&config.paths);
+    let mut browser = Browser::new(config, files);
+    browser.search();
+
+    let mut terminal = Terminal::open()?;
+    let mut buf = [0; 64];
+    loop {
+        let (rows, cols) = terminal.size();
+        let screen = browser.render(rows, cols);
+        terminal.tty.write_all(screen.as_bytes())?;
+        terminal.tty.flush()?;
+
+        let n = terminal.tty.read(&mut buf)?;
+        let mut changed = false;
+        for key in parse_keys(&buf[..n]) {
+            match key {
+                Key::Char(c) => {
+                    browser.query.push(c);
+                    changed = true;
+                }
+                Key::Backspace => changed |= browser.query.pop().is_some(),
+                Key::Clear => {
+                    changed = !browser.query.is_empty();
+                    browser.query.clear();
+                }
+                Key::Up => browser.select(-1),
+                Key::Down => browser.select(1),
+                Key::PageUp => browser.select(-(browser.list_height(rows) as isize)),
+                Key::PageDown => browser.select(browser.list_height(rows) as isize),
+                Key::Enter => {
+                    if let Some(hit) = browser.hits.get(browser.selected) {
+                        let path = browser.files[hit.file].clone();
+                        terminal.suspend()?;
+                        let opened = open_editor(&path, hit.line_number);
+                        terminal.resume()?;
+                        opened?;
+                    }
+                }
+                Key::Quit => return Ok(()),
+                Key::Other => {}
+            }
+        }
+        // Typing ahead is searched once for all of the keys read at once.
+        if changed {
+            browser.search();
+        }
+    }
+}
+
+// What the user typed, decoded from the bytes the terminal sends.
+#[derive(Debug, PartialEq)]
+enum Key {
+    Char(char),
+    Backspace,
+    // Ctrl-U clears the query, like in a shell.
+    Clear,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Enter,
+    Quit,
+    Other,
+}
+
+// Splits what was read from the terminal into keys. The arrow keys send
+// escape sequences like ESC [ A, while Esc on its own sends just ESC. Both
+// arrive in one read, so an ESC at the end of what was read is the Esc key.
+fn parse_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let key = match bytes[i] {
+            0x1b if i + 1 == bytes.len() => Key::Quit,
+            0x1b if matches!(bytes[i + 1], b'[' | b'O') => {
+                // The sequence ends with a byte from @ to ~, with any
+                // parameters before it.
+                let start = i + 2;
+                let len = bytes[start..]
+                    .iter()
+                    .position(|b| (0x40..=0x7e).contains(b))
+                    .map_or(bytes.len() - start, |p| p + 1);
+                i = start + len;
+                keys.push(match &bytes[start..i] {
+                    b"A" => Key::Up,
+                    b"B" => Key::Down,
+                    b"5~" => Key::PageUp,
+                    b"6~" => Key::PageDown,
+                    _ => Key::Other,
+                });
+                continue;
+            }
+            // Alt with a key sends ESC followed by the key.
+            0x1b => {
+                i += 2;
+                keys.push(Key::Other);
+                continue;
+            }
+            // Ctrl-C, Ctrl-D and Ctrl-Q
+            0x03 | 0x04 | 0x11 => Key::Quit,
+            // Ctrl-N and Ctrl-P
+            0x0e => Key::Down,
+            0x10 => Key::Up,
+            0x15 => Key::Clear,
+            b'\r' | b'\n' => Key::Enter,
+            0x7f | 0x08 => Key::Backspace,
+            b if b < 0x20 => Key::Other,
+            b => {
+                // The length of a UTF-8 char is in its first byte.
+                let len = match b {
+                    0xf0.. => 4,
+                    0xe0.. => 3,
+                    0xc0.. => 2,
+                    _ => 1,
+                };
+                let end = (i + len).min(bytes.len());
+                let key = std::str::from_utf8(&bytes[i..end])
+                    .ok()
+                    .and_then(|s| s.chars().next())
+                    .map_or(Key::Other, Key::Char);
+                i = end;
+                keys.push(key);
+                continue;
+            }
+        };
+        keys.push(key);
+        i += 1;
+    }
+    keys
+}
+
+// A matching line, by the index of its file in Browser::files.
+struct Hit {
+    file: usize,
+    line_number: usize,
+    text: String,
+    spans: Vec<Range<usize>>,
+}
+
+struct Browser {
+    config: Config,
+    files: Vec<PathBuf>,
+    query: String,
+    hits: Vec<Hit>,
+    // True if searching stopped at MAX_HITS.
+    truncated: bool,
+    // Why the query can't be searched for, like an invalid regex.
+    error: Option<String>,
+    selected: usize,
+    // The index of the first hit shown in the list.
+    scroll: usize,
+}
+
+impl Browser {
+    fn new(mut config: Config, files: Vec<PathBuf>) -> Browser {
+        let query = config.patterns.pop().unwrap_or_default();
+        Browser {
+            config,
+            files,
+            query,
+            hits: Vec::new(),
+            truncated: false,
+            error: None,
+            selected: 0,
+            scroll: 0,
+        }
+    }
+
+    fn search(&mut self) {
+        self.hits.clear();
+        self.truncated = false;
+        self.error = None;
+        self.selected = 0;
+        self.scroll = 0;
+        if self.query.is_empty() {
+            return;
+        }
+        if self.config.fuzzy.is_some() && self.query.chars().count() > Fuzzy::MAX_LEN {
+            self.error = Some("fuzzy queries can be at most 64 chars long".to_string());
+            return;
+        }
+        self.config.patterns = vec![self.query.clone()];
+        let matcher = match self.config.matcher() {
+            Ok(matcher) => matcher,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+
+        for (file, path) in self.files.iter().enumerate() {
+            // Files that can't be read are left out, like binary files.
+            let mut contents = Vec::new();
+            let read = decompress::open(path, self.config.search_zip)
+                .and_then(|mut reader| reader.read_to_end(&mut contents));
+            if read.is_err() || walk::is_binary(&contents) {
+                continue;
+            }
+            let contents = String::from_utf8_lossy(&contents);
+            for found in search(matcher.as_ref(), &contents) {
+                if self.hits.len() == MAX_HITS {
+                    self.truncated = true;
+                    return;
+                }
+                self.hits.push(Hit {
+                    file,
+                    line_number: found.line_number,
+                    text: found.line.to_string(),
+                    spans: found.spans,
+                });
+            }
+        }
+    }
+
+    // Moves the selection by the number of hits, stopping at either end.
+    fn select(&mut self, by: isize) {
+        let last = self.hits.len().saturating_sub(1);
+        self.selected = self.selected.saturating_add_signed(by).min(last);
+    }
+
+    // The screen has the prompt and a status line at the top, then the list
+    // of hits, then a rule with the file name above the preview.
+    fn list_height(&self, rows: usize) -> usize {
+        rows.saturating_sub(3 + self.preview_height(rows)).max(1)
+    }
+
+    fn preview_height(&self, rows: usize) -> usize {
+        (2 * PREVIEW_CONTEXT + 1).min(rows.saturating_sub(3) / 2)
+    }
+
+    // Draws the whole screen. Every row is cleared before it is written, so
+    // nothing is left over from the last screen.
+    fn render(&mut self, rows: usize, cols: usize) -> String {
+        // The cursor is hidden while drawing, so it doesn't flicker.
+        let mut screen = String::from("\x1b[?25l");
+        let row = |screen: &mut String, n: usize, text: &str| {
+            screen.push_str(&format!("\x1b[{n};1H\x1b[2K{text}"));
+        };
+
+        row(
+            &mut screen,
+            1,
+            &fit(&format!("> {}", self.query), &[], cols),
+        );
+        let status = match &self.error {
+            Some(error) => format!("\x1b[31m{}\x1b[0m", fit(error, &[], cols)),
+            None => {
+                let files = self
+                    .hits
+                    .iter()
+                    .map(|hit| hit.file)
+                    .collect::<HashSet<_>>()
+                    .len();
+                let matches = if self.truncated {
+                    format!("{}+ matches", self.hits.len())
+                } else {
+                    count(self.hits.len(), "match", "matches")
+                };
+                let text = format!(
+                    "{matches} in {}, Enter opens $EDITOR, Esc quits",
+                    count(files, "file", "files")
+                );
+                format!("\x1b[2m{}\x1b[0m", fit(&text, &[], cols))
+            }
+        };
+        row(&mut screen, 2, &status);
+
+        // Scroll just enough to keep the selection in view.
+        let height = self.list_height(rows);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + height {
+            self.scroll = self.selected + 1 - height;
+        }
+        for i in 0..height {
+            let text = match self.hits.get(self.scroll + i) {
+                Some(hit) => {
+                    let marker = if self.scroll + i == self.selected {
+                        "\x1b[1m>\x1b[0m "
+                    } else {
+                        "  "
+                    };
+                    let name = display_name(&self.files[hit.file]);
+                    let prefix = format!("{name}:{}:", hit.line_number);
+                    let width = cols.saturating_sub(2 + prefix.chars().count());
+                    format!(
+                        "{marker}{}{}{}",
+                        paint(FILE_NAME_COLOR, &format!("{name}:")),
+                        paint(LINE_NUMBER_COLOR, &format!("{}:", hit.line_number)),
+                        fit(&hit.text, &hit.spans, width)
+                    )
+                }
+                None => String::new(),
+            };
+            row(&mut screen, 3 + i, &text);
+        }
+
+        let preview_row = 3 + height;
+        let hit = self.hits.get(self.selected);
+        let rule = match hit {
+            Some(hit) => format!("── {} ", display_name(&self.files[hit.file])),
+            None => String::new(),
+        };
+        let rule = format!("{rule:─<cols$}");
+        row(
+            &mut screen,
+            preview_row,
+            &paint(SEPARATOR_COLOR, &fit(&rule, &[], cols)),
+        );
+        let lines = match hit {
+            Some(hit) => preview(
+                &self.files[hit.file],
+                hit.line_number,
+                self.config.search_zip,
+            ),
+            None => Vec::new(),
+        };
+        for i in 0..self.preview_height(rows) {
+            let text = match (lines.get(i), hit) {
+                (Some((number, text)), Some(hit)) => {
+                    let spans: &[Range<usize>] = if *number == hit.line_number {
+                        &hit.spans
+                    } else {
+                        &[]
+                    };
+                    format!(
+                        "{} {}",
+                        paint(LINE_NUMBER_COLOR, &format!("{number:>6}")),
+                        fit(text, spans, cols.saturating_sub(7))
+                    )
+                }
+                _ => String::new(),
+            };
+            row(&mut screen, preview_row + 1 + i, &text);
+        }
+
+        // The cursor goes back to the end of the query.
+        let col = (3 + self.query.chars().count()).min(cols.max(1));
+        screen.push_str(&format!("\x1b[1;{col}H\x1b[?25h"));
+        screen
+    }
+}
+
+// Reads the lines around a line of a file for the preview.
+fn preview(path: &Path, line_number: usize, search_zip: bool) -> Vec<(usize, String)> {
+    let Ok(mut reader) = decompress::open(path, search_zip) else {
+        return Vec::new();
+    };
+    let first = line_number.saturating_sub(PREVIEW_CONTEXT).max(1);
+    let mut lines = Vec::new();
+    let mut buf = Vec::new();
+    for number in 1..=line_number + PREVIEW_CONTEXT {
+        buf.clear();
+        match reader.read_until(b'\n', &mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        if number >= first {
+            let text = String::from_utf8_lossy(&buf);
+            lines.push((number, text.trim_end_matches(['\n', '\r']).to_string()));
+        }
+    }
+    lines
+}
+
+// Cuts the text down to the width of the screen, highlighting the spans in
+// it. Tabs and other control chars would move the cursor, so they are shown
+// as spaces and ? instead.
+fn fit(text: &str, spans: &[Range<usize>], width: usize) -> String {
+    let mut fitted = String::new();
+    let mut used = 0;
+    let mut highlighted = false;
+    for (i, c) in text.char_indices() {
+        let in_span = spans.iter().any(|span| span.contains(&i));
+        if in_span != highlighted {
+            if in_span {
+                fitted.push_str(&format!("\x1b[{MATCH_COLOR}m"));
+            } else {
+                fitted.push_str("\x1b[0m");
+            }
+            highlighted = in_span;
+        }
+        let (shown, len) = match c {
+            '\t' => (' ', 4),
+            c if c.is_control() => ('?', 1),
+            c => (c, 1),
+        };
+        if used + len > width {
+            break;
+        }
+        for _ in 0..len {
+            fitted.push(shown);
+        }
+        used += len;
+    }
+    if highlighted {
+        fitted.push_str("\x1b[0m");
+    }
+    fitted
+}
+
+fn paint(color: &str, text: &str) -> String {
+    format!("\x1b[{color}m{text}\x1b[0m")
+}
+
+// Opens the file at the line in $VISUAL or $EDITOR, falling back to vi. Like
+// the editors themselves, the variables can have arguments after the program.
+fn open_editor(path: &Path, line_number: usize) -> io::Result<()> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    let status = Command::new(program)
+        .args(words)
+        .arg(format!("+{line_number}"))
+        .arg(path)
+        .status()
+        .map_err(|e| io::Error::new(e.kind(), format!("couldn't run `{program}`: {e}")))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "`{program}` exited with {status}"
+        )))
+    }
+}
+
+// The controlling terminal, which is used even if standard input and output
+// are redirected. It goes back to how it was when dropped.
+struct Terminal {
+    tty: File,
+    // The settings from `stty -g`, which stty can restore.
+    saved: String,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Terminal> {
+        let tty = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/tty")
+            .map_err(|e| {
+                io::Error::new(e.kind(), format!("--interactive needs a terminal: {e}"))
+            })?;
+        let saved = stty(&tty, &["-g"])?;
+        let mut terminal = Terminal { tty, saved };
+        terminal.resume()?;
+        Ok(terminal)
+    }
+
+    // Raw mode hands over every key as it is pressed, without echoing it.
+    // Reads wait for at least one byte.
+    fn resume(&mut self) -> io::Result<()> {
+        stty(&self.tty, &["raw", "-echo", "min", "1", "time", "0"])?;
+        self.tty.write_all(b"\x1b[?1049h")
+    }
+
+    // Gives the terminal back, like before running an editor.
+    fn suspend(&mut self) -> io::Result<()> {
+        self.tty.write_all(b"\x1b[?1049l")?;
+        self.tty.flush()?;
+        stty(&self.tty, &[self.saved.as_str()]).map(|_| ())
+    }
+
+    // Returns the rows and columns of the terminal, or the classic 24 by 80
+    // if stty doesn't know.
+    fn size(&self) -> (usize, usize) {
+        let size = stty(&self.tty, &["size"]).unwrap_or_default();
+        let mut numbers = size.split_whitespace().map(str::parse);
+        match (numbers.next(), numbers.next()) {
+            (Some(Ok(rows)), Some(Ok(cols))) if rows > 0 && cols > 0 => (rows, cols),
+            _ => (24, 80),
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.suspend();
+    }
+}
+
+// Runs stty on the terminal, which it reads the settings of from its
+// standard input.
+fn stty(tty: &File, args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(tty.try_clone()?)
+        .stderr(Stdio::inherit())
+        .output()
+        .map_err(|e| io::Error::new(e.kind(), format!("couldn't run `stty`: {e}")))?;
+    if !output.status.success() {
+        return Err(io::Error::other("`stty` couldn't set up the terminal"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    #[test]
+    fn keys() {
+        assert_eq!(
+            vec![
+                Key::Char('a'),
+                Key::Char('é'),
+                Key::Up,
+                Key::PageDown,
+                Key::Backspace,
+                Key::Enter,
+                Key::Other,
+                Key::Quit
+            ],
+            parse_keys("aé\x1b[A\x1b[6~\x7f\r\x1bx\x1b".as_bytes())
+        );
+        assert_eq!(vec![Key::Quit], parse_keys(b"\x03"));
+    }
+
+    #[test]
+    fn fit_to_width() {
+        assert_eq!("a    b", fit("a\tbc", &[], 6));
+        assert_eq!(
+            "\x1b[1;31mc\x1b[0ma\x1b[1;31mfé\x1b[0m",
+            fit("café au lait", &[0..1, 2..5], 4)
+        );
+    }
+
+    #[test]
+    fn browse() {
+        let dir = TempDir::new("tui");
+        fs::write(
+            dir.join("poem.txt"),
+            "I'm nobody! Who are you?\nAre you nobody, too?\n",
+        )
+        .unwrap();
+        let config = Config {
+            patterns: vec!["nobody".to_string()],
+            paths: vec![dir.to_string_lossy().into_owned()],
+            ..Config::default()
+        };
+        let files = vec![dir.join("poem.txt")];
+        let mut browser = Browser::new(config, files);
+        browser.search();
+        assert_eq!(2, browser.hits.len());
+        browser.select(5);
+        assert_eq!(1, browser.selected);
+
+        let screen = browser.render(12, 40);
+        assert!(screen.contains("> nobody"));
+        assert!(screen.contains("2 matches in 1 file,"));
+        assert!(screen.contains("Are you \x1b[1;31mnobody\x1b[0m, too?"));
+
+        browser.query = "(".to_string();
+        browser.config.regex = true;
+        browser.search();
+        assert!(browser.error.is_some());
+    }
+}
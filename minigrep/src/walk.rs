@@ -0,0 +1,408 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{index::INDEX_FILE, MinigrepError};
+
+// The names of the files that hold ignore rules. They are read in this order,
+// so rules in .ignore win over rules in .gitignore.
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+// How many bytes at the start of a file are checked for a NUL byte when
+// deciding whether the file is binary. This is the same heuristic grep uses.
+const BINARY_CHECK_LEN: usize = 8192;
+
+/// Expands the paths given on the command line into the files to search.
+pub struct Walker {
+    /// Search hidden files and directories, i.e. ones whose name starts with
+    /// a dot.
+    pub hidden: bool,
+    /// Honor .gitignore and .ignore files, and skip .git directories even
+    /// when searching hidden ones.
+    pub ignore: bool,
+}
+
+impl Walker {
+    /// Returns every file to search under the given paths. Directories are
+    /// walked recursively in sorted order so the output is deterministic.
+    /// Paths given explicitly are always searched, even if they are hidden or
+    /// ignored.
+    ///
+    /// Directories that can't be read are left out, and returned along with
+    /// the files so they can be reported without stopping the walk.
+    pub fn walk(&self, paths: &[String]) -> (Vec<PathBuf>, Vec<MinigrepError>) {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        for path in paths {
+            let path = PathBuf::from(path);
+            if path.is_dir() {
+                self.walk_dir(&path, &mut Vec::new(), &mut files, &mut errors);
+            } else {
+                files.push(path);
+            }
+        }
+        (files, errors)
+    }
+
+    fn walk_dir(
+        &self,
+        dir: &Path,
+        ignores: &mut Vec<Ignore>,
+        files: &mut Vec<PathBuf>,
+        errors: &mut Vec<MinigrepError>,
+    ) {
+        let error = |path: &Path, source| MinigrepError::File {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        let entries = fs::read_dir(dir).and_then(|entries| entries.collect::<Result<Vec<_>, _>>());
+        let mut entries = match entries {
+            Ok(entries) => entries,
+            Err(e) => {
+                errors.push(error(dir, e));
+                return;
+            }
+        };
+
+        // The rules in a directory's ignore files only apply to the things
+        // inside of it, so they are pushed before walking and popped after.
+        // Without its rules the directory is still walked.
+        let pushed = self.ignore
+            && match Ignore::from_dir(dir) {
+                Ok(ignore) if !ignore.rules.is_empty() => {
+                    ignores.push(ignore);
+                    true
+                }
+                Ok(_) => false,
+                Err(e) => {
+                    errors.push(error(dir, e));
+                    false
+                }
+            };
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let path = entry.path();
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(e) => {
+                    errors.push(error(&path, e));
+                    continue;
+                }
+            };
+            // Like grep -r, symbolic links found while walking aren't
+            // followed. A link to a directory would otherwise be read as a
+            // file, a dangling one can't be read at all, and links can form
+            // cycles. Links given on the command line are still followed.
+            if file_type.is_symlink() {
+                continue;
+            }
+            let is_dir = file_type.is_dir();
+
+            if !self.hidden && entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // A repository's .git directory isn't in its .gitignore, but it
+            // is never what anyone means to search, so it is left out along
+            // with the ignored files even when hidden ones are searched.
+            if self.ignore && is_dir && entry.file_name() == ".git" {
+                continue;
+            }
+            if is_ignored(ignores, &path, is_dir) {
+                continue;
+            }
+            // minigrep's own index is never worth searching.
+            if entry.file_name() == INDEX_FILE {
+                continue;
+            }
+
+            if is_dir {
+                self.walk_dir(&path, ignores, files, errors);
+            } else {
+                files.push(path);
+            }
+        }
+
+        if pushed {
+            ignores.pop();
+        }
+    }
+}
+
+/// Returns true if the contents look like a binary file rather than text.
+pub fn is_binary(contents: &[u8]) -> bool {
+    contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0)
+}
+
+// Rules from deeper directories are checked last so they override the rules
+// of their parents, the same way later lines in one file override earlier ones.
+fn is_ignored(ignores: &[Ignore], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for ignore in ignores {
+        if let Some(result) = ignore.matched(path, is_dir) {
+            ignored = result;
+        }
+    }
+    ignored
+}
+
+// The rules from the ignore files in one directory.
+struct Ignore {
+    base: PathBuf,
+    rules: Vec<Rule>,
+}
+
+struct Rule {
+    glob: Vec<char>,
+    // A leading ! re-includes a path that an earlier rule ignored.
+    negated: bool,
+    // A trailing / only matches directories.
+    dir_only: bool,
+    // Patterns containing a / are matched against the path relative to the
+    // ignore file, others are matched against the file name at any depth.
+    anchored: bool,
+}
+
+impl Ignore {
+    fn from_dir(dir: &Path) -> io::Result<Ignore> {
+        let mut rules = Vec::new();
+        for name in IGNORE_FILES {
+            match fs::read_to_string(dir.join(name)) {
+                Ok(contents) => rules.extend(contents.lines().filter_map(Rule::parse)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Ignore {
+            base: dir.to_path_buf(),
+            rules,
+        })
+    }
+
+    // Returns Some(true) if the path is ignored, Some(false) if it was
+    // re-included with a negated rule, and None if no rule matched it.
+    fn matched(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        let relative = path.strip_prefix(&self.base).ok()?;
+        let relative: Vec<char> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+            .chars()
+            .collect();
+        let name: Vec<char> = path.file_name()?.to_string_lossy().chars().collect();
+
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                if rule.dir_only && !is_dir {
+                    return false;
+                }
+                let text = if rule.anchored { &relative } else { &name };
+                glob_match(&rule.glob, text)
+            })
+            .map(|rule| !rule.negated)
+    }
+}
+
+impl Rule {
+    fn parse(line: &str) -> Option<Rule> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line.strip_prefix('\\').unwrap_or(line)),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        if line.is_empty() {
+            return None;
+        }
+
+        Some(Rule {
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+}
+
+// Matches gitignore style globs. `*` and `?` don't match a /, `**` matches
+// any number of directories and [...] matches a set of chars.
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    match glob.first() {
+        None => text.is_empty(),
+        Some('*') if glob.get(1) == Some(&'*') => {
+            // `**/` can also match zero directories.
+            let rest = &glob[2..];
+            if let Some(after_slash) = rest.strip_prefix(&['/']) {
+                if glob_match(after_slash, text) {
+                    return true;
+                }
+            }
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some('*') => {
+            let rest = &glob[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            matches!(text.first(), Some(c) if *c != '/') && glob_match(&glob[1..], &text[1..])
+        }
+        Some('[') => match (glob.iter().skip(2).position(|&c| c == ']'), text.first()) {
+            (Some(end), Some(&c)) => {
+                // The first char after [ (or [!) can be a literal ].
+                let end = end + 2;
+                let (negated, set) = match glob[1] {
+                    '!' | '^' => (true, &glob[2..end]),
+                    _ => (false, &glob[1..end]),
+                };
+                c != '/'
+                    && class_contains(set, c) != negated
+                    && glob_match(&glob[end + 1..], &text[1..])
+            }
+            (None, Some(&c)) => c == '[' && glob_match(&glob[1..], &text[1..]),
+            (_, None) => false,
+        },
+        Some('\\') if glob.len() > 1 => {
+            text.first() == Some(&glob[1]) && glob_match(&glob[2..], &text[1..])
+        }
+        Some(&g) => text.first() == Some(&g) && glob_match(&glob[1..], &text[1..]),
+    }
+}
+
+fn class_contains(set: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == '-' {
+            if set[i] <= c && c <= set[i + 2] {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn glob(glob: &str, text: &str) -> bool {
+        let glob: Vec<char> = glob.chars().collect();
+        let text: Vec<char> = text.chars().collect();
+        glob_match(&glob, &text)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("*.rs", "main.rs"));
+        assert!(!glob("*.rs", "src/main.rs"));
+        assert!(glob("src/*.rs", "src/main.rs"));
+        assert!(glob("**/main.rs", "main.rs"));
+        assert!(glob("**/main.rs", "a/b/main.rs"));
+        assert!(glob("a/**/b", "a/x/y/b"));
+        assert!(glob("file?.[ch]", "file1.c"));
+        assert!(!glob("file?.[!ch]", "file1.c"));
+    }
+
+    #[test]
+    fn rules() {
+        let ignore = Ignore {
+            base: PathBuf::from("root"),
+            rules: ["target/", "*.log", "!keep.log", "/docs/*.md"]
+                .into_iter()
+                .filter_map(Rule::parse)
+                .collect(),
+        };
+        let matched = |path: &str, is_dir| ignore.matched(Path::new(path), is_dir);
+
+        assert_eq!(Some(true), matched("root/a/target", true));
+        assert_eq!(None, matched("root/a/target", false));
+        assert_eq!(Some(true), matched("root/a/debug.log", false));
+        assert_eq!(Some(false), matched("root/keep.log", false));
+        assert_eq!(Some(true), matched("root/docs/a.md", false));
+        assert_eq!(None, matched("root/src/docs/a.md", false));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_are_skipped() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TempDir::new("symlinks");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/poem.txt"), "I'm nobody!").unwrap();
+        symlink(dir.join("sub"), dir.join("dir-link")).unwrap();
+        symlink(dir.join("missing"), dir.join("dangling")).unwrap();
+
+        let walker = Walker {
+            hidden: false,
+            ignore: true,
+        };
+        let (files, errors) = walker.walk(&[dir.to_string_lossy().into_owned()]);
+        assert_eq!(vec![dir.join("sub/poem.txt")], files);
+        assert!(errors.is_empty());
+
+        // A link named on the command line is followed.
+        let (files, _) = walker.walk(&[dir.join("dir-link").to_string_lossy().into_owned()]);
+        assert_eq!(vec![dir.join("dir-link/poem.txt")], files);
+    }
+
+    #[test]
+    fn git_dir_is_skipped() {
+        let dir = TempDir::new("git");
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".git/config"), "[core]").unwrap();
+        fs::write(dir.join(".env"), "KEY=nobody").unwrap();
+        fs::write(dir.join("poem.txt"), "I'm nobody!").unwrap();
+
+        let walk = |ignore| {
+            let walker = Walker {
+                hidden: true,
+                ignore,
+            };
+            walker.walk(&[dir.to_string_lossy().into_owned()]).0
+        };
+        assert_eq!(vec![dir.join(".env"), dir.join("poem.txt")], walk(true));
+        assert_eq!(
+            vec![
+                dir.join(".env"),
+                dir.join(".git/config"),
+                dir.join("poem.txt")
+            ],
+            walk(false)
+        );
+    }
+
+    #[test]
+    fn binary() {
+        assert!(is_binary(b"ELF\0\x01"));
+        assert!(!is_binary("Rust:\nTrust me.".as_bytes()));
+    }
+}
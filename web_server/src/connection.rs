@@ -0,0 +1,184 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::TcpStream,
+    time::Duration,
+};
+
+use crate::{signal, ParseError, Request, Response, Router};
+
+/// How long a connection can sit idle waiting for the next request before it
+/// is closed. While it waits, it holds on to one of the pool's workers.
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(5);
+/// The most requests answered on one connection before it is closed, so no
+/// client can keep a worker forever.
+pub const MAX_REQUESTS: usize = 100;
+
+/// Answers the requests sent on the connection until the client closes it,
+/// asks for it to be closed, it is idle for longer than [`IDLE_TIMEOUT`] or
+/// the server is shutting down.
+///
+/// Requests are read one after another from the same buffered reader, so a
+/// client can pipeline them: send several without waiting for the responses,
+/// which come back in the same order.
+pub fn handle_connection(stream: TcpStream, router: &Router) {
+    // A client that stops reading would leave the worker stuck writing to it,
+    // so writes time out too.
+    if let Err(e) = stream
+        .set_read_timeout(Some(IDLE_TIMEOUT))
+        .and_then(|()| stream.set_write_timeout(Some(IDLE_TIMEOUT)))
+    {
+        println!("Couldn't set the connection's timeouts: {e}");
+        return;
+    }
+
+    let mut buf_reader = BufReader::new(&stream);
+    // If the client has gone away there is nothing else to do, and panicking
+    // would take the worker down.
+    if let Err(e) = serve(&mut buf_reader, &mut &stream, router) {
+        println!("Connection error: {e}");
+    }
+}
+
+// Reads requests and writes their responses until the connection should be
+// closed.
+fn serve<R: BufRead, W: Write>(reader: &mut R, writer: &mut W, router: &Router) -> io::Result<()> {
+    for served in 1..=MAX_REQUESTS {
+        let (response, keep_alive) = match Request::read(reader) {
+            Ok(request) => {
+                println!("Request: {} {}", request.method, request.path);
+                let keep_alive = keep_alive(&request) && served < MAX_REQUESTS;
+                (router.handle(request), keep_alive)
+            }
+            // The client closed the connection, or left it idle too long.
+            Err(ParseError::Closed) => return Ok(()),
+            Err(ParseError::Io(e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Ok(())
+            }
+            Err(ParseError::Io(e)) => return Err(e),
+            // After a bad request we can't tell where the next one starts, so
+            // the connection is closed.
+            Err(e) => {
+                println!("Bad request: {e}");
+                (Response::text(e.status(), format!("{e}\n")), false)
+            }
+        };
+
+        // Once the server is shutting down, each connection gets the answer to
+        // the request it sent and is then closed. This is checked after
+        // answering, since that can take a while.
+        let keep_alive = keep_alive && !signal::shutdown_requested();
+        let response = if keep_alive {
+            response
+                .with_header("Connection", "keep-alive")
+                .with_header(
+                    "Keep-Alive",
+                    format!(
+                        "timeout={}, max={}",
+                        IDLE_TIMEOUT.as_secs(),
+                        MAX_REQUESTS - served
+                    ),
+                )
+        } else {
+            response.with_header("Connection", "close")
+        };
+        response.write_to(writer)?;
+        if !keep_alive {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Returns true if the client wants the connection kept open after the request.
+// HTTP/1.1 connections stay open unless the client says `Connection: close`,
+// while HTTP/1.0 ones are closed unless it says `Connection: keep-alive`.
+fn keep_alive(request: &Request) -> bool {
+    let has = |option: &str| {
+        request.headers("Connection").any(|value| {
+            value
+                .split(',')
+                .any(|token| token.trim().eq_ignore_ascii_case(option))
+        })
+    };
+    if request.version == "HTTP/1.0" {
+        has("keep-alive")
+    } else {
+        !has("close")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sends the requests all at once, as a pipelining client would, and
+    // returns the status of every response.
+    fn exchange(requests: &str) -> Vec<String> {
+        let router = Router::new().get("/", |_| Response::text(200, "hello"));
+        let mut output = Vec::new();
+        serve(&mut requests.as_bytes(), &mut output, &router).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        output
+            .split("HTTP/1.1 ")
+            .skip(1)
+            .map(|response| response.split("\r\n").next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn pipelined_requests() {
+        let responses = exchange(
+            "GET / HTTP/1.1\r\nHost: a\r\n\r\n\
+             GET /missing HTTP/1.1\r\nHost: a\r\n\r\n\
+             POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 4\r\n\r\nbody\r\n\
+             GET / HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        assert_eq!(
+            vec![
+                "200 OK",
+                "404 NOT FOUND",
+                "405 METHOD NOT ALLOWED",
+                "200 OK"
+            ],
+            responses
+        );
+    }
+
+    #[test]
+    fn closing_the_connection() {
+        let twice = |request: &str| exchange(&request.repeat(2)).len();
+        assert_eq!(2, twice("GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+        assert_eq!(
+            1,
+            twice("GET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n")
+        );
+        assert_eq!(1, twice("GET / HTTP/1.0\r\n\r\n"));
+        assert_eq!(2, twice("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n"));
+        // Nothing after a bad request is answered.
+        assert_eq!(1, twice("GET / HTTP/1.1\r\n\r\n"));
+
+        let many = "GET / HTTP/1.1\r\nHost: a\r\n\r\n".repeat(MAX_REQUESTS + 1);
+        assert_eq!(MAX_REQUESTS, exchange(&many).len());
+    }
+
+    #[test]
+    fn connection_headers() {
+        let router = Router::new().get("/", |_| Response::text(200, "hello"));
+        let mut output = Vec::new();
+        let requests = "GET / HTTP/1.1\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n";
+        serve(&mut requests.as_bytes(), &mut output, &router).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let (first, second) = output.split_once("hello").unwrap();
+        assert!(first.contains("Connection: keep-alive\r\n"));
+        assert!(first.contains(&format!(
+            "Keep-Alive: timeout=5, max={}\r\n",
+            MAX_REQUESTS - 1
+        )));
+        assert!(second.contains("Connection: close\r\n"));
+    }
+}
@@ -0,0 +1,457 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    ops::Range,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{Request, Response};
+
+/// Serves the files in a directory.
+///
+/// Responses say what the file is with `Content-Type`, and carry an `ETag` and
+/// `Last-Modified` so browsers can ask again with `If-None-Match` or
+/// `If-Modified-Since` and get 304 Not Modified instead of the file. A `Range`
+/// header asks for part of the file, which comes back as 206 Partial Content.
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+// What a Range header asks for.
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    // No range, or one we don't handle, so the whole file is sent.
+    Whole,
+    Part(Range<u64>),
+    // The range starts past the end of the file.
+    Unsatisfiable,
+}
+
+impl StaticFiles {
+    /// Creates a handler serving the files under the root directory.
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Answers the request with the file at the path, relative to the root.
+    /// The path usually comes from a wildcard, as in
+    /// `.get("/static/*path", move |r| files.serve(r, r.param("path").unwrap()))`.
+    ///
+    /// Paths leading out of the root with `..` are refused with 403 Forbidden.
+    /// For a directory, its `index.html` is served.
+    pub fn serve(&self, request: &Request, path: &str) -> Response {
+        let file = match self.resolve(path) {
+            Some(file) => file,
+            None => return Response::text(403, "Forbidden\n"),
+        };
+        match serve_file(request, &file) {
+            Ok(response) => response,
+            Err(e) => match e.kind() {
+                io::ErrorKind::NotFound => Response::text(404, "Not Found\n"),
+                io::ErrorKind::PermissionDenied => Response::text(403, "Forbidden\n"),
+                _ => {
+                    println!("Couldn't read {}: {e}", file.display());
+                    Response::text(500, "Internal Server Error\n")
+                }
+            },
+        }
+    }
+
+    // Turns the path into a file under the root, or None if it would lead out
+    // of it.
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let mut file = self.root.clone();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return None,
+                // A backslash separates paths on Windows, and a NUL would cut
+                // the path short.
+                _ if segment.contains(['\\', '\0']) => return None,
+                _ => file.push(segment),
+            }
+        }
+
+        // A symbolic link could still point out of the root, so the real
+        // paths are compared. Files that don't exist are left for opening to
+        // report.
+        if let (Ok(root), Ok(real)) = (self.root.canonicalize(), file.canonicalize()) {
+            if !real.starts_with(root) {
+                return None;
+            }
+        }
+        Some(file)
+    }
+}
+
+fn serve_file(request: &Request, path: &Path) -> io::Result<Response> {
+    let mut path = path.to_path_buf();
+    let mut file = File::open(&path)?;
+    let mut metadata = file.metadata()?;
+    if metadata.is_dir() {
+        path.push("index.html");
+        file = File::open(&path)?;
+        metadata = file.metadata()?;
+    }
+
+    let len = metadata.len();
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    // The size and the time it was changed tell versions of a file apart
+    // without reading it.
+    let etag = format!("\"{modified:x}-{len:x}\"");
+    let last_modified = http_date(modified);
+
+    let mut response = Response::new(200)
+        .with_header("ETag", &etag)
+        .with_header("Last-Modified", &last_modified);
+    if not_modified(request, &etag, modified) {
+        response.status = 304;
+        return Ok(response);
+    }
+
+    let mut response = response
+        .with_header("Content-Type", content_type(&path))
+        .with_header("Accept-Ranges", "bytes");
+    // If-Range asks for the range only if the file is still the version the
+    // client has the rest of. Otherwise it needs all of it again.
+    let same_version = request
+        .header("If-Range")
+        .is_none_or(|v| v == etag || parse_http_date(v) == Some(modified));
+    let range = match request.header("Range") {
+        Some(range) if same_version => byte_range(range, len),
+        _ => ByteRange::Whole,
+    };
+    match range {
+        ByteRange::Whole => {
+            file.read_to_end(&mut response.body)?;
+        }
+        ByteRange::Part(range) => {
+            file.seek(SeekFrom::Start(range.start))?;
+            file.take(range.end - range.start)
+                .read_to_end(&mut response.body)?;
+            response.status = 206;
+            response = response.with_header(
+                "Content-Range",
+                format!("bytes {}-{}/{len}", range.start, range.end - 1),
+            );
+        }
+        ByteRange::Unsatisfiable => {
+            response.status = 416;
+            response = response.with_header("Content-Range", format!("bytes */{len}"));
+        }
+    }
+    Ok(response)
+}
+
+// Returns true if the client's copy of the file is up to date. If-None-Match
+// is checked first since an ETag is more precise than a time in seconds.
+fn not_modified(request: &Request, etag: &str, modified: u64) -> bool {
+    if let Some(tags) = request.header("If-None-Match") {
+        // Weak tags count too, as the file is the same either way.
+        let weak = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+        return tags
+            .split(',')
+            .any(|tag| tag.trim() == "*" || weak(tag) == weak(etag));
+    }
+    match request
+        .header("If-Modified-Since")
+        .and_then(parse_http_date)
+    {
+        Some(since) => modified <= since,
+        None => false,
+    }
+}
+
+// Reads a Range header like `bytes=0-99`, `bytes=100-` or `bytes=-100` (the
+// last 100 bytes). Several ranges can be asked for at once, but then we send
+// the whole file, which RFC 9110 allows.
+fn byte_range(header: &str, len: u64) -> ByteRange {
+    let spec = match header.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return ByteRange::Whole,
+    };
+    let (first, last) = match spec.split_once('-') {
+        Some(bounds) => bounds,
+        None => return ByteRange::Whole,
+    };
+    let number = |s: &str| -> Option<u64> {
+        if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        s.parse().ok()
+    };
+
+    if first.is_empty() {
+        return match number(last) {
+            Some(0) => ByteRange::Unsatisfiable,
+            Some(_) if len == 0 => ByteRange::Unsatisfiable,
+            Some(suffix) => ByteRange::Part(len.saturating_sub(suffix)..len),
+            None => ByteRange::Whole,
+        };
+    }
+    let start = match number(first) {
+        Some(start) => start,
+        None => return ByteRange::Whole,
+    };
+    let end = if last.is_empty() {
+        len
+    } else {
+        match number(last) {
+            Some(last) if last >= start => last.saturating_add(1).min(len),
+            _ => return ByteRange::Whole,
+        }
+    };
+    if start >= len {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Part(start..end)
+    }
+}
+
+/// Returns the media type for the file's extension, or
+/// `application/octet-stream` when it isn't known, which browsers download
+/// rather than show.
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match &extension[..] {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+const DAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// Formats a time in seconds since the Unix epoch the way HTTP headers write
+// dates, e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(secs: u64) -> String {
+    let days = secs / 86400;
+    let (year, month, day) = civil_from_days(days);
+    let time = secs % 86400;
+    format!(
+        "{}, {day:02} {} {year} {:02}:{:02}:{:02} GMT",
+        // The epoch was a Thursday.
+        DAYS[((days + 4) % 7) as usize],
+        MONTHS[month as usize - 1],
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// Reads a date written like `http_date` writes them back into seconds since
+// the Unix epoch. The obsolete formats HTTP allows aren't understood, so the
+// header they're in is ignored.
+fn parse_http_date(date: &str) -> Option<u64> {
+    let parts: Vec<&str> = date.split_whitespace().collect();
+    let (day, month, year, time) = match parts[..] {
+        [_, day, month, year, time, "GMT"] => (day, month, year, time),
+        _ => return None,
+    };
+    let day: u64 = day.parse().ok()?;
+    let month = MONTHS.iter().position(|&m| m == month)? as u64 + 1;
+    let year: u64 = year.parse().ok()?;
+    let mut time = time.split(':').map(|n| n.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if year < 1970 || !(1..=31).contains(&day) || hours > 23 || minutes > 59 || seconds > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    Some(days * 86400 + hours * 3600 + minutes * 60 + seconds)
+}
+
+// These convert between days since the epoch and dates, using Howard
+// Hinnant's algorithms for the proleptic Gregorian calendar. Years are split
+// into 400 year eras, which repeat exactly, and years are counted from March
+// so the leap day comes last.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year % 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let day_of_year = (153 * mp + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+    use std::fs;
+
+    fn get(files: &StaticFiles, path: &str, headers: &str) -> Response {
+        let request = format!("GET /{path} HTTP/1.0\r\n{headers}\r\n");
+        let request = Request::read(&mut request.as_bytes()).unwrap();
+        files.serve(&request, path)
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", http_date(0));
+        assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", http_date(784111777));
+        assert_eq!("Thu, 29 Feb 2024 23:59:59 GMT", http_date(1709251199));
+        for secs in [0, 784111777, 951782400, 1709251199, 4102444800] {
+            assert_eq!(Some(secs), parse_http_date(&http_date(secs)));
+        }
+        assert_eq!(None, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+        assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 08:49 GMT"));
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(ByteRange::Part(0..100), byte_range("bytes=0-99", 1000));
+        assert_eq!(ByteRange::Part(900..1000), byte_range("bytes=900-", 1000));
+        assert_eq!(ByteRange::Part(900..1000), byte_range("bytes=-100", 1000));
+        assert_eq!(ByteRange::Part(0..10), byte_range("bytes=-100", 10));
+        assert_eq!(ByteRange::Part(5..10), byte_range("bytes=5-99", 10));
+        assert_eq!(ByteRange::Unsatisfiable, byte_range("bytes=10-", 10));
+        assert_eq!(ByteRange::Unsatisfiable, byte_range("bytes=-0", 10));
+        assert_eq!(ByteRange::Whole, byte_range("bytes=0-1,5-6", 10));
+        assert_eq!(ByteRange::Whole, byte_range("bytes=5-1", 10));
+        assert_eq!(ByteRange::Whole, byte_range("items=0-1", 10));
+        assert_eq!(ByteRange::Whole, byte_range("bytes=+1-2", 10));
+    }
+
+    #[test]
+    fn content_types() {
+        assert_eq!(
+            "text/html; charset=utf-8",
+            content_type(Path::new("a/INDEX.HTML"))
+        );
+        assert_eq!("image/png", content_type(Path::new("ferris.png")));
+        assert_eq!(
+            "application/octet-stream",
+            content_type(Path::new("Makefile"))
+        );
+    }
+
+    #[test]
+    fn serves_files() {
+        let root = TempDir::new("files");
+        fs::create_dir_all(root.join("docs")).unwrap();
+        fs::write(root.join("docs/index.html"), "<h1>Docs</h1>").unwrap();
+        // Not valid UTF-8, which fs::read_to_string couldn't have served.
+        let image = [0x89, b'P', b'N', b'G', 0xff, 0x00, 0x01, 0x02];
+        fs::write(root.join("ferris.png"), image).unwrap();
+        let files = StaticFiles::new(&*root);
+
+        let response = get(&files, "ferris.png", "");
+        assert_eq!(200, response.status);
+        assert_eq!(Some("image/png"), response.header("Content-Type"));
+        assert_eq!(&image[..], &response.body[..]);
+
+        let response = get(&files, "docs/", "");
+        assert_eq!(
+            Some("text/html; charset=utf-8"),
+            response.header("Content-Type")
+        );
+        assert_eq!(b"<h1>Docs</h1>", &response.body[..]);
+
+        assert_eq!(404, get(&files, "missing.png", "").status);
+        assert_eq!(403, get(&files, "../etc/passwd", "").status);
+        assert_eq!(403, get(&files, "docs/../../etc/passwd", "").status);
+
+        let response = get(&files, "ferris.png", "Range: bytes=1-3\r\n");
+        assert_eq!(206, response.status);
+        assert_eq!(Some("bytes 1-3/8"), response.header("Content-Range"));
+        assert_eq!(b"PNG", &response.body[..]);
+        let response = get(&files, "ferris.png", "Range: bytes=8-\r\n");
+        assert_eq!(416, response.status);
+        assert_eq!(Some("bytes */8"), response.header("Content-Range"));
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let root = TempDir::new("cache");
+        fs::write(root.join("poem.txt"), "I'm nobody! Who are you?").unwrap();
+        let files = StaticFiles::new(&*root);
+
+        let response = get(&files, "poem.txt", "");
+        let etag = response.header("ETag").unwrap().to_string();
+        let modified = response.header("Last-Modified").unwrap().to_string();
+
+        let response = get(&files, "poem.txt", &format!("If-None-Match: {etag}\r\n"));
+        assert_eq!(304, response.status);
+        assert!(response.body.is_empty());
+        assert_eq!(Some(&etag[..]), response.header("ETag"));
+        let response = get(
+            &files,
+            "poem.txt",
+            &format!("If-None-Match: \"x\", W/{etag}\r\n"),
+        );
+        assert_eq!(304, response.status);
+        assert_eq!(
+            200,
+            get(&files, "poem.txt", "If-None-Match: \"x\"\r\n").status
+        );
+
+        let response = get(
+            &files,
+            "poem.txt",
+            &format!("If-Modified-Since: {modified}\r\n"),
+        );
+        assert_eq!(304, response.status);
+        let earlier = http_date(parse_http_date(&modified).unwrap() - 1);
+        let response = get(
+            &files,
+            "poem.txt",
+            &format!("If-Modified-Since: {earlier}\r\n"),
+        );
+        assert_eq!(200, response.status);
+
+        // The range is only sent if the client has the same version.
+        let headers = format!("Range: bytes=0-2\r\nIf-Range: {etag}\r\n");
+        assert_eq!(206, get(&files, "poem.txt", &headers).status);
+        let headers = "Range: bytes=0-2\r\nIf-Range: \"old\"\r\n";
+        let response = get(&files, "poem.txt", headers);
+        assert_eq!(200, response.status);
+        assert_eq!(24, response.body.len());
+    }
+}
@@ -1,8 +1,24 @@
 use std::{
     sync::{mpsc, Arc, Mutex},
     thread,
+    time::{Duration, Instant},
 };
 
+pub mod connection;
+pub mod files;
+pub mod request;
+pub mod response;
+pub mod router;
+pub mod signal;
+#[cfg(test)]
+mod test_util;
+
+pub use connection::handle_connection;
+pub use files::StaticFiles;
+pub use request::{ParseError, Request};
+pub use response::Response;
+pub use router::Router;
+
 pub struct ThreadPool {
     workers: Vec<Worker>,
     sender: Option<mpsc::Sender<Job>>,
@@ -47,7 +63,7 @@ impl ThreadPool {
     ///
     /// The `build` function will return an error if the size is zero.
     pub fn build(size: usize) -> Result<ThreadPool, PoolCreationError> {
-        if size <= 0 {
+        if size == 0 {
             return Err(PoolCreationError);
         }
 
@@ -63,10 +79,47 @@ impl ThreadPool {
 
         self.sender.as_ref().unwrap().send(job).unwrap();
     }
+
+    /// Shut down the ThreadPool, waiting at most `timeout` for the workers.
+    ///
+    /// The pool stops taking jobs, but the ones already sent are still run.
+    /// Returns false if some workers were still busy when the time ran out.
+    /// Those are left running, and end when the process exits.
+    pub fn shutdown(mut self, timeout: Duration) -> bool {
+        // Dropping the sender makes the workers' recv fail once the jobs that
+        // were sent have been taken, which is their signal to stop.
+        drop(self.sender.take());
+
+        let deadline = Instant::now() + timeout;
+        let busy = |workers: &[Worker]| {
+            workers
+                .iter()
+                .any(|worker| worker.thread.as_ref().is_some_and(|t| !t.is_finished()))
+        };
+        while busy(&self.workers) {
+            if Instant::now() >= deadline {
+                // There is no way to join a thread with a timeout, so the busy
+                // ones are let go rather than joined when the pool is dropped.
+                for worker in &mut self.workers {
+                    if worker.thread.as_ref().is_some_and(|t| !t.is_finished()) {
+                        println!("Worker {} is still busy; leaving it.", worker.id);
+                        worker.thread = None;
+                    }
+                }
+                return false;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        true
+    }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
+        // Without this, the workers would wait for jobs forever and joining
+        // them would never return.
+        drop(self.sender.take());
+
         for worker in &mut self.workers {
             println!("Shutting down worker {}", worker.id);
 
@@ -109,3 +162,33 @@ impl Worker {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn shutdown_runs_the_jobs_already_sent() {
+        let pool = ThreadPool::new(2);
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..8 {
+            let done = Arc::clone(&done);
+            pool.execute(move || {
+                thread::sleep(Duration::from_millis(10));
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        assert!(pool.shutdown(Duration::from_secs(10)));
+        assert_eq!(8, done.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn shutdown_gives_up_at_the_deadline() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| thread::sleep(Duration::from_secs(1)));
+        let start = Instant::now();
+        assert!(!pool.shutdown(Duration::from_millis(50)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+    }
+}
@@ -1,21 +1,55 @@
 use std::{
     fs,
-    io::{BufRead, BufReader, Write},
     net::{TcpListener, TcpStream},
+    sync::Arc,
     thread,
     time::Duration,
 };
-use web_server::ThreadPool;
+use web_server::{handle_connection, signal, Response, Router, StaticFiles, ThreadPool};
+
+// How often the server checks whether it has been asked to shut down.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// How long requests being answered get to finish once shutting down. This is
+// longer than a connection can sit idle, so idle connections have time to
+// time out.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
 
 fn main() {
     // This will listen to incoming tcp connections.
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
+    let address = listener.local_addr().unwrap();
+    if let Err(e) = signal::listen_for_shutdown() {
+        println!("Couldn't listen for shutdown signals: {e}");
+    }
+    // A signal doesn't interrupt accepting, so once the server is asked to
+    // shut down this thread connects to it to wake the loop below. Checking
+    // here rather than in the loop means accepting never waits on it.
+    thread::spawn(move || {
+        while !signal::shutdown_requested() {
+            thread::sleep(POLL_INTERVAL);
+        }
+        let _ = TcpStream::connect(address);
+    });
     let pool = ThreadPool::new(4);
+    // The router is shared by every worker, so it is behind an Arc.
+    let router = Arc::new(routes());
 
     // The incoming method gives us a stream of incoming connections. These are
     // actually connection attempts.
-    for stream in listener.incoming().take(2) {
-        let stream = stream.unwrap();
+    for stream in listener.incoming() {
+        // The connection that woke us up, or one that came in just as we were
+        // shutting down, is closed without an answer.
+        if signal::shutdown_requested() {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                // Like running out of file descriptors, which may pass.
+                println!("Couldn't accept a connection: {e}");
+                continue;
+            }
+        };
 
         println!("Connection established!");
         // This is single threaded.
@@ -28,43 +62,42 @@ fn main() {
         // });
 
         // This is using a thread pool to handle requests.
-        pool.execute(|| {
-            handle_connection(stream);
+        let router = Arc::clone(&router);
+        pool.execute(move || {
+            handle_connection(stream, &router);
         });
     }
 
     println!("Shutting down.");
+    // Stop taking connections, then let the workers finish the ones they have.
+    drop(listener);
+    if !pool.shutdown(SHUTDOWN_TIMEOUT) {
+        println!("Gave up waiting for requests to finish.");
+    }
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    // This will collect the lines of request that the browser sends to the server.
-    let http_request: Vec<_> = buf_reader
-        .lines()
-        .map(|result| result.unwrap())
-        // Take lines until there is an empty line.
-        .take_while(|line| !line.is_empty())
-        .collect();
-
-    println!("Request: {:#?}", http_request);
-
-    let request_line = &http_request[0];
-
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
+// This is where the paths the server answers are set up.
+fn routes() -> Router {
+    let files = StaticFiles::new("static");
+    Router::new()
+        .get("/", |_| html_file(200, "hello.html"))
+        .get("/sleep", |_| {
             thread::sleep(Duration::from_secs(5));
-            ("HTTP/1.1 200 OK", "hello.html")
-        }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
-    };
-
-    // Read in the HTML file and return it as the response.
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
-
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+            html_file(200, "hello.html")
+        })
+        .get("/static/*path", move |r| {
+            files.serve(r, r.param("path").unwrap_or_default())
+        })
+        .not_found(|_| html_file(404, "404.html"))
+}
 
-    // Write an OK message back to the client.
-    stream.write_all(response.as_bytes()).unwrap();
+// Read in the HTML file and return it as the response.
+fn html_file(status: u16, filename: &str) -> Response {
+    match fs::read_to_string(filename) {
+        Ok(contents) => Response::html(status, contents),
+        Err(e) => {
+            println!("Couldn't read {filename}: {e}");
+            Response::text(500, "Internal Server Error\n")
+        }
+    }
 }
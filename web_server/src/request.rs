@@ -0,0 +1,521 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read},
+};
+
+// The longest request line or header line the server reads. Browsers stay well
+// under this, and it keeps a client from making the server buffer forever.
+const MAX_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+// Empty lines skipped before a request line. There is no reason to send more
+// than one, so a client can't keep a worker busy sending nothing else.
+const MAX_EMPTY_LINES: usize = 8;
+/// The largest body the server accepts, whether it is sent with a
+/// `Content-Length` or in chunks.
+pub const MAX_BODY: usize = 1024 * 1024;
+
+/// An HTTP/1.x request read from a connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    /// The method, like `GET` or `POST`. Methods are case-sensitive.
+    pub method: String,
+    /// The path of the target with percent-escapes decoded, e.g. `/hello`.
+    pub path: String,
+    /// Everything after the `?` in the target, still encoded.
+    pub query: Option<String>,
+    /// `HTTP/1.1` or `HTTP/1.0`.
+    pub version: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    // Filled in by the router from the route the path matched.
+    pub(crate) params: Vec<(String, String)>,
+}
+
+/// Why a request couldn't be read.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The connection was closed before a request was sent.
+    Closed,
+    /// Reading from the connection failed, e.g. because it timed out.
+    Io(io::Error),
+    /// The request isn't valid HTTP. The reason says what's wrong with it.
+    Malformed(&'static str),
+    /// A line or the body is longer than the server accepts.
+    TooLarge,
+    /// The request is for a version other than HTTP/1.0 or HTTP/1.1.
+    Version,
+}
+
+impl Request {
+    /// Reads one request from the reader, including its body.
+    ///
+    /// The body is read according to the `Content-Length` header or, if the
+    /// request has `Transfer-Encoding: chunked`, by joining its chunks.
+    /// Without either, the request doesn't have a body.
+    pub fn read<R: BufRead>(reader: &mut R) -> Result<Request, ParseError> {
+        // Some clients send a CRLF after a request's body, so RFC 9112 says
+        // empty lines before the request line should be ignored.
+        let mut skipped = 0;
+        let line = loop {
+            match read_line(reader)? {
+                Some(line) if line.is_empty() && skipped < MAX_EMPTY_LINES => skipped += 1,
+                Some(line) => break line,
+                None => return Err(ParseError::Closed),
+            }
+        };
+        let mut parts = line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if parts.next().is_none() => {
+                (method, target, version)
+            }
+            _ => return Err(ParseError::Malformed("invalid request line")),
+        };
+        if !is_token(method) {
+            return Err(ParseError::Malformed("invalid method"));
+        }
+        let version = parse_version(version)?;
+        let (path, query) = parse_target(method, target)?;
+
+        let headers = read_headers(reader)?;
+        let mut request = Request {
+            method: method.to_string(),
+            path,
+            query,
+            version: version.to_string(),
+            headers,
+            body: Vec::new(),
+            params: Vec::new(),
+        };
+        // HTTP/1.1 clients have to say which host they want, so servers
+        // serving several hosts from one address can tell them apart.
+        if request.version == "HTTP/1.1" && request.header("Host").is_none() {
+            return Err(ParseError::Malformed("missing Host header"));
+        }
+        request.body = read_body(reader, &request)?;
+        Ok(request)
+    }
+
+    /// Returns the value of the header with the name, ignoring its case. If the
+    /// header was sent more than once, the first value is returned.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns every value of the header with the name, ignoring its case.
+    pub fn headers<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.headers
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the path parameter with the name, e.g. `id` for a request to
+    /// `/users/7` handled by the route `/users/:id`, or `path` for the wildcard
+    /// in `/static/*path`.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl ParseError {
+    /// The status code of the response telling the client what went wrong.
+    pub fn status(&self) -> u16 {
+        match self {
+            ParseError::TooLarge => 413,
+            ParseError::Version => 505,
+            _ => 400,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Closed => write!(f, "connection closed"),
+            ParseError::Io(e) => write!(f, "{e}"),
+            ParseError::Malformed(reason) => write!(f, "{reason}"),
+            ParseError::TooLarge => write!(f, "request too large"),
+            ParseError::Version => write!(f, "unsupported HTTP version"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> ParseError {
+        ParseError::Io(e)
+    }
+}
+
+// Reads a line ending in CRLF, returning it without the line ending, or None
+// if the reader is at its end. A bare LF is accepted too, as RFC 9112
+// suggests.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(if line.len() as u64 + 1 >= MAX_LINE {
+            ParseError::TooLarge
+        } else {
+            ParseError::Malformed("unexpected end of request")
+        });
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::Malformed("request isn't valid UTF-8"))
+}
+
+fn parse_version(version: &str) -> Result<&str, ParseError> {
+    match version {
+        "HTTP/1.1" | "HTTP/1.0" => Ok(version),
+        _ => {
+            let number = version
+                .strip_prefix("HTTP/")
+                .ok_or(ParseError::Malformed("invalid HTTP version"))?;
+            // Other versions are well-formed, just not something we speak.
+            match number.as_bytes() {
+                [major, b'.', minor] if major.is_ascii_digit() && minor.is_ascii_digit() => {
+                    Err(ParseError::Version)
+                }
+                _ => Err(ParseError::Malformed("invalid HTTP version")),
+            }
+        }
+    }
+}
+
+// Splits the request target into its decoded path and the query string. Besides
+// the usual `/path?query`, proxies send absolute URLs like
+// `http://host/path?query`, and `OPTIONS *` asks about the whole server.
+fn parse_target(method: &str, target: &str) -> Result<(String, Option<String>), ParseError> {
+    if target == "*" && method == "OPTIONS" {
+        return Ok((target.to_string(), None));
+    }
+    let target = match target
+        .strip_prefix("http://")
+        .or_else(|| target.strip_prefix("https://"))
+    {
+        Some(rest) => rest.find('/').map_or("/", |slash| &rest[slash..]),
+        None => target,
+    };
+    if !target.starts_with('/') {
+        return Err(ParseError::Malformed("invalid request target"));
+    }
+    // The fragment is only for the browser, but a client could still send one.
+    let target = target.split('#').next().unwrap_or(target);
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+    Ok((percent_decode(path)?, query))
+}
+
+// Decodes the `%XX` escapes in a path.
+fn percent_decode(path: &str) -> Result<String, ParseError> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or(ParseError::Malformed("invalid percent-encoding in path"))?;
+            decoded.push(hex);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| ParseError::Malformed("path isn't valid UTF-8"))
+}
+
+fn read_headers<R: BufRead>(reader: &mut R) -> Result<Vec<(String, String)>, ParseError> {
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::Malformed("unexpected end of request"))?;
+        // The headers end with an empty line.
+        if line.is_empty() {
+            return Ok(headers);
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(ParseError::TooLarge);
+        }
+        if line.starts_with([' ', '\t']) {
+            // Continuing a header on the next line was allowed once, but RFC
+            // 9112 says to reject it.
+            return Err(ParseError::Malformed("obsolete header line folding"));
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::Malformed("invalid header"))?;
+        // No whitespace is allowed between the name and the colon, since
+        // servers and proxies disagreeing on the name can be abused.
+        if !is_token(name) {
+            return Err(ParseError::Malformed("invalid header name"));
+        }
+        headers.push((
+            name.to_string(),
+            value.trim_matches([' ', '\t']).to_string(),
+        ));
+    }
+}
+
+fn read_body<R: BufRead>(reader: &mut R, request: &Request) -> Result<Vec<u8>, ParseError> {
+    if let Some(encoding) = request.header("Transfer-Encoding") {
+        // A message with both could be read differently by a proxy in front of
+        // the server, which is how requests get smuggled past it.
+        if request.header("Content-Length").is_some() {
+            return Err(ParseError::Malformed(
+                "both Transfer-Encoding and Content-Length",
+            ));
+        }
+        // Chunked is the only coding we can undo.
+        if !encoding.eq_ignore_ascii_case("chunked")
+            || request.headers("Transfer-Encoding").count() > 1
+        {
+            return Err(ParseError::Malformed("unsupported transfer encoding"));
+        }
+        return read_chunked(reader);
+    }
+
+    let mut lengths = request.headers("Content-Length");
+    let length = match lengths.next() {
+        Some(length) => length,
+        None => return Ok(Vec::new()),
+    };
+    if lengths.any(|other| other != length) {
+        return Err(ParseError::Malformed("conflicting Content-Length headers"));
+    }
+    let length = parse_length(length, 10)?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(unexpected_end)?;
+    Ok(body)
+}
+
+// Reads a body sent in chunks, each preceded by its size in hex, up to a chunk
+// of size zero. The trailer headers after the last chunk are skipped.
+fn read_chunked<R: BufRead>(reader: &mut R) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::Malformed("unexpected end of request"))?;
+        // A chunk size can be followed by extensions, which we don't use.
+        let size = line.split(';').next().unwrap_or(&line).trim_end();
+        let size = parse_length(size, 16)?;
+        if size == 0 {
+            break;
+        }
+        if body.len() + size > MAX_BODY {
+            return Err(ParseError::TooLarge);
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader
+            .read_exact(&mut body[start..])
+            .map_err(unexpected_end)?;
+        if read_line(reader)?.as_deref() != Some("") {
+            return Err(ParseError::Malformed("invalid chunk"));
+        }
+    }
+    read_headers(reader)?;
+    Ok(body)
+}
+
+// Parses a body or chunk length, which has to be all digits, unlike what
+// `parse` accepts.
+fn parse_length(length: &str, radix: u32) -> Result<usize, ParseError> {
+    if length.is_empty() || !length.chars().all(|c| c.is_digit(radix)) {
+        return Err(ParseError::Malformed("invalid length"));
+    }
+    match usize::from_str_radix(length, radix) {
+        Ok(length) if length <= MAX_BODY => Ok(length),
+        _ => Err(ParseError::TooLarge),
+    }
+}
+
+fn unexpected_end(e: io::Error) -> ParseError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        ParseError::Malformed("unexpected end of request")
+    } else {
+        ParseError::Io(e)
+    }
+}
+
+// Methods and header names are tokens: letters, digits and a few symbols.
+fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(request: &str) -> Result<Request, ParseError> {
+        Request::read(&mut request.as_bytes())
+    }
+
+    fn reason(request: &str) -> &'static str {
+        match parse(request) {
+            Err(ParseError::Malformed(reason)) => reason,
+            other => panic!("expected a malformed request, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn request_line_and_headers() {
+        let request = parse(
+            "GET /hello%20world?name=ferris&x=1 HTTP/1.1\r\n\
+             Host: localhost:7878\r\n\
+             accept:  text/html \r\n\
+             X-Tag: a\r\n\
+             x-tag: b\r\n\
+             \r\n",
+        )
+        .unwrap();
+        assert_eq!("GET", request.method);
+        assert_eq!("/hello world", request.path);
+        assert_eq!(Some("name=ferris&x=1"), request.query.as_deref());
+        assert_eq!("HTTP/1.1", request.version);
+        assert_eq!(Some("text/html"), request.header("Accept"));
+        assert_eq!(Some("localhost:7878"), request.header("HOST"));
+        assert_eq!(vec!["a", "b"], request.headers("X-Tag").collect::<Vec<_>>());
+        assert_eq!(None, request.header("Content-Length"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn other_targets() {
+        let request = parse("GET http://localhost/sleep?x HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+        assert_eq!(
+            ("/sleep", Some("x")),
+            (&request.path[..], request.query.as_deref())
+        );
+        let request = parse("OPTIONS * HTTP/1.0\n\n").unwrap();
+        assert_eq!("*", request.path);
+        // Empty lines before the request line are skipped.
+        let request = parse("\r\n\nGET /hello HTTP/1.0\r\n\r\n").unwrap();
+        assert_eq!("/hello", request.path);
+    }
+
+    #[test]
+    fn content_length_body() {
+        let mut input =
+            "POST /echo HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\n\r\nhello GET".as_bytes();
+        let request = Request::read(&mut input).unwrap();
+        assert_eq!(b"hello", &request.body[..]);
+        // Anything after the body is left for the next request.
+        assert_eq!(b" GET", input);
+    }
+
+    #[test]
+    fn chunked_body() {
+        let request = parse(
+            "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nhello\r\n7;ext=1\r\n, world\r\n0\r\nTrailer: x\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(b"hello, world", &request.body[..]);
+        assert_eq!(None, request.header("Trailer"));
+    }
+
+    #[test]
+    fn malformed_requests() {
+        assert!(matches!(parse(""), Err(ParseError::Closed)));
+        assert!(matches!(parse("\r\n\n"), Err(ParseError::Closed)));
+        assert_eq!(
+            "invalid request line",
+            reason(&"\r\n".repeat(MAX_EMPTY_LINES + 1))
+        );
+        assert_eq!("invalid request line", reason("GET /\r\n\r\n"));
+        assert_eq!("invalid request line", reason("GET  / HTTP/1.1\r\n\r\n"));
+        assert_eq!("invalid method", reason("G(T / HTTP/1.1\r\n\r\n"));
+        assert_eq!(
+            "invalid request target",
+            reason("GET hello HTTP/1.1\r\n\r\n")
+        );
+        assert_eq!(
+            "invalid percent-encoding in path",
+            reason("GET /%4 HTTP/1.0\r\n\r\n")
+        );
+        assert_eq!("invalid HTTP version", reason("GET / HTTP/one\r\n\r\n"));
+        assert!(matches!(
+            parse("GET / HTTP/2.0\r\n\r\n"),
+            Err(ParseError::Version)
+        ));
+        assert_eq!("missing Host header", reason("GET / HTTP/1.1\r\n\r\n"));
+        assert_eq!("invalid header", reason("GET / HTTP/1.0\r\nHost\r\n\r\n"));
+        assert_eq!(
+            "invalid header name",
+            reason("GET / HTTP/1.0\r\nHost : a\r\n\r\n")
+        );
+        assert_eq!(
+            "obsolete header line folding",
+            reason("GET / HTTP/1.0\r\nX: a\r\n b\r\n\r\n")
+        );
+        assert_eq!(
+            "unexpected end of request",
+            reason("GET / HTTP/1.0\r\nHost: a\r\n")
+        );
+    }
+
+    #[test]
+    fn malformed_bodies() {
+        let post =
+            |headers: &str, body: &str| parse(&format!("POST / HTTP/1.0\r\n{headers}\r\n{body}"));
+        let reason = |headers, body| match post(headers, body) {
+            Err(ParseError::Malformed(reason)) => reason,
+            other => panic!("expected a malformed request, got {other:?}"),
+        };
+        assert_eq!("invalid length", reason("Content-Length: +5\r\n", "hello"));
+        assert_eq!(
+            "unexpected end of request",
+            reason("Content-Length: 6\r\n", "hello")
+        );
+        assert_eq!(
+            "conflicting Content-Length headers",
+            reason("Content-Length: 5\r\nContent-Length: 6\r\n", "hello")
+        );
+        assert_eq!(
+            "both Transfer-Encoding and Content-Length",
+            reason("Transfer-Encoding: chunked\r\nContent-Length: 5\r\n", "")
+        );
+        assert_eq!(
+            "unsupported transfer encoding",
+            reason("Transfer-Encoding: gzip\r\n", "")
+        );
+        assert_eq!(
+            "invalid chunk",
+            reason("Transfer-Encoding: chunked\r\n", "2\r\nhello\r\n0\r\n\r\n")
+        );
+        assert!(matches!(
+            post("Content-Length: 99999999999999999999\r\n", ""),
+            Err(ParseError::TooLarge)
+        ));
+        let long = format!("GET /{} HTTP/1.0\r\n\r\n", "a".repeat(MAX_LINE as usize));
+        assert!(matches!(parse(&long), Err(ParseError::TooLarge)));
+    }
+}
@@ -0,0 +1,122 @@
+use std::io::{self, Write};
+
+/// An HTTP response. The `Content-Length` header is worked out from the body
+/// when the response is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Creates a response with the status and an empty body.
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates a response with an HTML body.
+    pub fn html(status: u16, body: impl Into<String>) -> Response {
+        Response::new(status).with_body("text/html; charset=utf-8", body.into())
+    }
+
+    /// Creates a response with a plain text body.
+    pub fn text(status: u16, body: impl Into<String>) -> Response {
+        Response::new(status).with_body("text/plain; charset=utf-8", body.into())
+    }
+
+    /// Adds a header. Headers are written in the order they were added.
+    pub fn with_header(mut self, name: &str, value: impl Into<String>) -> Response {
+        self.headers.push((name.to_string(), value.into()));
+        self
+    }
+
+    /// Sets the body along with the `Content-Type` saying what it is.
+    pub fn with_body(self, content_type: &str, body: impl Into<Vec<u8>>) -> Response {
+        let mut response = self.with_header("Content-Type", content_type);
+        response.body = body.into();
+        response
+    }
+
+    /// Returns the value of the header with the name, ignoring its case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Writes the status line, the headers and the body.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        // Building the head first means it goes out in one write rather than
+        // a packet per header.
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        // A 304 has no body, and its Content-Length would be that of the body
+        // the client already has, so it is left out.
+        if self.status != 304 && self.status != 204 {
+            head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        }
+        head.push_str("\r\n");
+        out.write_all(head.as_bytes())?;
+        out.write_all(&self.body)?;
+        out.flush()
+    }
+}
+
+/// Returns the reason phrase that goes with a status code in the status line.
+pub fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "CREATED",
+        204 => "NO CONTENT",
+        206 => "PARTIAL CONTENT",
+        301 => "MOVED PERMANENTLY",
+        302 => "FOUND",
+        304 => "NOT MODIFIED",
+        400 => "BAD REQUEST",
+        403 => "FORBIDDEN",
+        404 => "NOT FOUND",
+        405 => "METHOD NOT ALLOWED",
+        413 => "PAYLOAD TOO LARGE",
+        416 => "RANGE NOT SATISFIABLE",
+        500 => "INTERNAL SERVER ERROR",
+        501 => "NOT IMPLEMENTED",
+        503 => "SERVICE UNAVAILABLE",
+        505 => "HTTP VERSION NOT SUPPORTED",
+        // Clients go by the code, so the phrase can be anything.
+        _ => "UNKNOWN",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_head_and_body() {
+        let response = Response::text(404, "gone\n").with_header("X-Tag", "a");
+        assert_eq!(
+            Some("text/plain; charset=utf-8"),
+            response.header("content-type")
+        );
+
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        assert_eq!(
+            "HTTP/1.1 404 NOT FOUND\r\n\
+             Content-Type: text/plain; charset=utf-8\r\n\
+             X-Tag: a\r\n\
+             Content-Length: 5\r\n\
+             \r\n\
+             gone\n",
+            String::from_utf8(out).unwrap()
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{Request, Response};
+
+/// A function answering the requests for a route.
+pub type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync + 'static>;
+
+/// Sends each request to the handler registered for its method and path.
+///
+/// Paths are matched against patterns segment by segment. A segment starting
+/// with `:` matches any one segment and a final segment starting with `*`
+/// matches the rest of the path, so `/users/:id` matches `/users/7` and
+/// `/static/*path` matches `/static/css/site.css`. The matched values are
+/// available from [`Request::param`].
+///
+/// Routes are tried in the order they were added, so a route like `/users/new`
+/// has to be added before `/users/:id` to be reachable.
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Handler,
+}
+
+struct Route {
+    method: String,
+    segments: Vec<Segment>,
+    handler: Handler,
+}
+
+enum Segment {
+    Literal(String),
+    Param(String),
+    Wildcard(String),
+}
+
+impl Router {
+    /// Creates a router without routes. Every request gets a 404 response
+    /// until routes are added.
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            not_found: Box::new(|_| Response::text(404, "Not Found\n")),
+        }
+    }
+
+    /// Adds a route for the method and path pattern.
+    ///
+    /// # Panics
+    ///
+    /// The `route` function will panic if the pattern doesn't start with `/`,
+    /// a parameter doesn't have a name or a wildcard isn't the last segment.
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            segments: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Adds a route for GET requests.
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    /// Adds a route for POST requests.
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Sets the handler for requests whose path doesn't match any route.
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    /// Answers the request with the first route matching its method and path.
+    ///
+    /// If routes match the path but none of them the method, the response is
+    /// 405 Method Not Allowed with an `Allow` header listing the methods that
+    /// would have been. If no route matches the path, the not found handler
+    /// answers.
+    pub fn handle(&self, mut request: Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+        for route in &self.routes {
+            let params = match route.matches(&request.path) {
+                Some(params) => params,
+                None => continue,
+            };
+            if route.method == request.method {
+                request.params = params;
+                return (route.handler)(&request);
+            }
+            if !allowed.contains(&&route.method[..]) {
+                allowed.push(&route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            (self.not_found)(&request)
+        } else {
+            Response::text(405, "Method Not Allowed\n").with_header("Allow", allowed.join(", "))
+        }
+    }
+}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl Route {
+    // Returns the parameters if the path matches the route's pattern.
+    fn matches(&self, path: &str) -> Option<Vec<(String, String)>> {
+        let mut params = Vec::new();
+        // Paths start with a `/` like the patterns, except for `OPTIONS *`.
+        let mut parts = path.strip_prefix('/')?.split('/');
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => {
+                    if parts.next()? != literal {
+                        return None;
+                    }
+                }
+                Segment::Param(name) => match parts.next()? {
+                    "" => return None,
+                    part => params.push((name.clone(), part.to_string())),
+                },
+                Segment::Wildcard(name) => {
+                    // The wildcard can match nothing, but its slash has to be
+                    // there: `/static/` matches `/static/*path` and `/static`
+                    // doesn't.
+                    let rest: Vec<&str> = parts.by_ref().collect();
+                    if rest.is_empty() {
+                        return None;
+                    }
+                    params.push((name.clone(), rest.join("/")));
+                }
+            }
+        }
+        // Every part of the path has to be used up by the pattern.
+        match parts.next() {
+            Some(_) => None,
+            None => Some(params),
+        }
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let rest = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("route pattern {pattern:?} doesn't start with /"));
+    let parts: Vec<&str> = rest.split('/').collect();
+    parts
+        .iter()
+        .enumerate()
+        .map(|(i, part)| {
+            if let Some(name) = part.strip_prefix(':') {
+                assert!(!name.is_empty(), "unnamed parameter in route {pattern:?}");
+                Segment::Param(name.to_string())
+            } else if let Some(name) = part.strip_prefix('*') {
+                assert!(!name.is_empty(), "unnamed wildcard in route {pattern:?}");
+                assert!(
+                    i == parts.len() - 1,
+                    "wildcard isn't the last segment of route {pattern:?}"
+                );
+                Segment::Wildcard(name.to_string())
+            } else {
+                Segment::Literal(part.to_string())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(router: &Router, method: &str, path: &str) -> Response {
+        let request = format!("{method} {path} HTTP/1.0\r\n\r\n");
+        router.handle(Request::read(&mut request.as_bytes()).unwrap())
+    }
+
+    fn body(response: Response) -> String {
+        String::from_utf8(response.body).unwrap()
+    }
+
+    fn router() -> Router {
+        Router::new()
+            .get("/", |_| Response::text(200, "home"))
+            .get("/users/new", |_| Response::text(200, "new user"))
+            .get("/users/:id", |r| {
+                Response::text(200, format!("user {}", r.param("id").unwrap()))
+            })
+            .post("/users/:id", |_| Response::new(204))
+            .get("/users/:id/posts/:post", |r| {
+                let (id, post) = (r.param("id").unwrap(), r.param("post").unwrap());
+                Response::text(200, format!("post {post} by {id}"))
+            })
+            .get("/static/*path", |r| {
+                Response::text(200, format!("file {:?}", r.param("path").unwrap()))
+            })
+    }
+
+    #[test]
+    fn routes_by_path() {
+        let router = router();
+        assert_eq!("home", body(get(&router, "GET", "/")));
+        assert_eq!("new user", body(get(&router, "GET", "/users/new")));
+        assert_eq!("user 7", body(get(&router, "GET", "/users/7")));
+        assert_eq!("post 2 by 7", body(get(&router, "GET", "/users/7/posts/2")));
+        assert_eq!(204, get(&router, "POST", "/users/7").status);
+    }
+
+    #[test]
+    fn wildcards_take_the_rest() {
+        let router = router();
+        assert_eq!(
+            "file \"css/site.css\"",
+            body(get(&router, "GET", "/static/css/site.css"))
+        );
+        assert_eq!("file \"\"", body(get(&router, "GET", "/static/")));
+        assert_eq!(404, get(&router, "GET", "/static").status);
+    }
+
+    #[test]
+    fn not_found_and_method_not_allowed() {
+        let router = router();
+        assert_eq!(404, get(&router, "GET", "/users").status);
+        assert_eq!(404, get(&router, "GET", "/users/").status);
+        assert_eq!(404, get(&router, "GET", "/users/7/").status);
+        assert_eq!(404, get(&router, "GET", "/nope").status);
+
+        let response = get(&router, "DELETE", "/users/7");
+        assert_eq!(405, response.status);
+        assert_eq!(Some("GET, POST"), response.header("Allow"));
+
+        let router = router.not_found(|r| Response::text(404, format!("no {}", r.path)));
+        assert_eq!("no /nope", body(get(&router, "GET", "/nope")));
+    }
+
+    #[test]
+    #[should_panic(expected = "wildcard isn't the last segment")]
+    fn wildcard_must_be_last() {
+        Router::new().get("/static/*path/x", |_| Response::new(200));
+    }
+}
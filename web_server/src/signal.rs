@@ -0,0 +1,71 @@
+use std::{
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+// Set when the server has been asked to shut down. A signal handler can run in
+// the middle of anything, so storing to an atomic is about all it can safely
+// do.
+static SHUTDOWN: AtomicBool = AtomicBool::new(false);
+
+/// Makes SIGINT (Ctrl-C) and SIGTERM ask the server to shut down instead of
+/// killing it, so it can finish the requests it is answering. A second signal
+/// kills it as usual, in case shutting down takes too long.
+///
+/// On platforms without signals this does nothing.
+pub fn listen_for_shutdown() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        sys::install(sys::SIGINT)?;
+        sys::install(sys::SIGTERM)?;
+    }
+    Ok(())
+}
+
+/// Returns true once the server has been asked to shut down.
+pub fn shutdown_requested() -> bool {
+    SHUTDOWN.load(Ordering::SeqCst)
+}
+
+/// Asks the server to shut down, as a signal would.
+pub fn request_shutdown() {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+}
+
+// The standard library doesn't handle signals, but it links against the C
+// library on Unix, so its `signal` function can be declared and called
+// directly.
+#[cfg(unix)]
+mod sys {
+    use std::{io, os::raw::c_int};
+
+    // These are the same on Linux and the BSDs, including macOS.
+    pub const SIGINT: c_int = 2;
+    pub const SIGTERM: c_int = 15;
+    const SIG_DFL: usize = 0;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        // The handler is really a function pointer, or SIG_DFL to go back to
+        // the default action.
+        fn signal(signum: c_int, handler: usize) -> usize;
+    }
+
+    extern "C" fn handle(signum: c_int) {
+        super::request_shutdown();
+        // The next one gets the default action, which ends the process.
+        // SAFETY: signal is async-signal-safe, so it can be called here.
+        unsafe {
+            signal(signum, SIG_DFL);
+        }
+    }
+
+    pub fn install(signum: c_int) -> io::Result<()> {
+        let handler: extern "C" fn(c_int) = handle;
+        // SAFETY: handle only does what is allowed in a signal handler.
+        if unsafe { signal(signum, handler as usize) } == SIG_ERR {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
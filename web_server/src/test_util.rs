@@ -0,0 +1,38 @@
+// Helpers shared by the tests of several modules.
+
+use std::{
+    env, fs,
+    ops::Deref,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// A directory for a test's files. It is removed when it goes out of scope,
+/// so it is cleaned up even when an assertion fails first.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates an empty directory. The name has the process id in it, so the
+    /// same test in two runs at the same time doesn't share it.
+    pub fn new(name: &str) -> TempDir {
+        let path = env::temp_dir().join(format!("web_server-{name}-{}", process::id()));
+        // One left over from a run that was killed could have anything in it.
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        TempDir(path)
+    }
+}
+
+impl Deref for TempDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}